@@ -1,15 +1,34 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
 use clap::Parser;
 
+use crate::config::BindAddr;
+use crate::server::history::StoreKind;
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
-    /// Port to listen on
-    #[arg(long, default_value_t = 22)]
-    pub port: u16,
+    /// Optional TOML file with server settings. Command line options take precedence over it
+    #[arg(short, long, value_name = "FILE")]
+    pub config: Option<String>,
+
+    /// Port to listen on, unless the bind address has its own [default: 22]
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// Address to listen on, e.g. "[::]:2222". Can be repeated [default: 0.0.0.0]
+    #[arg(long, value_name = "ADDR")]
+    pub bind: Vec<BindAddr>,
 
-    /// Private key to identify server with. Defaults to a temporary ed25519 key
+    /// Private key to identify server with. Can be repeated to offer several key types.
+    /// Defaults to an ed25519 key generated once in the state directory
     #[arg(short = 'i', long, value_name = "KEY")]
-    pub identity: Option<String>,
+    pub identity: Vec<String>,
+
+    /// Directory to keep the generated host key and other state in [default: ~/.local/share/chatd]
+    #[arg(long, value_name = "DIR")]
+    pub state_dir: Option<PathBuf>,
 
     /// Optional file of public keys who are operators
     #[arg(long, value_name = "FILE")]
@@ -19,10 +38,38 @@ pub struct Cli {
     #[arg(long, value_name = "FILE")]
     pub whitelist: Option<String>,
 
+    /// Write /op, /deop and /whitelist changes back to the oplist and whitelist files
+    #[arg(long)]
+    pub save_keys: bool,
+
+    /// Optional file to persist bans across restarts
+    #[arg(long, value_name = "FILE")]
+    pub bans: Option<String>,
+
     /// Optional file with a message of the day or welcome message
     #[arg(long, value_name = "FILE")]
     pub motd: Option<String>,
 
+    /// Optional file to persist the chat history across restarts
+    #[arg(long, value_name = "FILE")]
+    pub history: Option<String>,
+
+    /// Format of the chat history file [default: jsonl]
+    #[arg(long, value_name = "FORMAT", value_enum)]
+    pub history_store: Option<StoreKind>,
+
+    /// Number of messages replayed to users joining a room [default: 20]
+    #[arg(long, value_name = "N")]
+    pub history_len: Option<usize>,
+
+    /// Mark users as away after being idle this long, e.g. "10m". 0 to disable [default: 10m]
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub idle_away: Option<Duration>,
+
+    /// Disconnect users after being idle this long, e.g. "2h"
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub idle_disconnect: Option<Duration>,
+
     /// Write chat log to this file
     #[arg(long, value_name = "FILE")]
     pub log: Option<String>,
@@ -0,0 +1,277 @@
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::{de, Deserialize, Deserializer};
+
+use crate::cli::Cli;
+use crate::server::history::{StoreKind, DEFAULT_INPUT_HISTORY_EXCLUDE};
+use crate::server::host_keys;
+use crate::server::memo::{DEFAULT_MEMO_EXPIRY, DEFAULT_MEMO_MAX};
+use crate::server::{IdleLimits, RoomConfig, Theme, TimestampMode};
+use crate::utils;
+
+const DEFAULT_PORT: u16 = 22;
+const DEFAULT_BIND: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+const DEFAULT_IDLE_AWAY: Duration = Duration::from_secs(10 * 60);
+/// SSH level inactivity timeout, used unless idle members are disconnected by the room
+const DEFAULT_INACTIVITY_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// Address to listen on. The port may be left out, in which case the
+/// `--port` one is used, e.g. `0.0.0.0`, `[::]:2222` or `127.0.0.1:2223`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BindAddr {
+    ip: IpAddr,
+    port: Option<u16>,
+}
+
+impl BindAddr {
+    fn with_default_port(&self, port: u16) -> SocketAddr {
+        SocketAddr::new(self.ip, self.port.unwrap_or(port))
+    }
+}
+
+impl FromStr for BindAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(addr) = s.parse::<SocketAddr>() {
+            return Ok(Self {
+                ip: addr.ip(),
+                port: Some(addr.port()),
+            });
+        }
+
+        // IPv6 addresses may be bracketed even without a port
+        let ip = s
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(s);
+        match ip.parse::<IpAddr>() {
+            Ok(ip) => Ok(Self { ip, port: None }),
+            Err(_) => Err(format!(
+                "invalid address '{}', expected an IP address with an optional port, \
+                e.g. 0.0.0.0, [::]:2222 or 127.0.0.1:2223",
+                s
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for BindAddr {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(d)?;
+        BindAddr::from_str(&value).map_err(de::Error::custom)
+    }
+}
+
+/// Contents of the `--config` file. Every key is optional, most of them
+/// mirror the command line option of the same name.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct FileConfig {
+    port: Option<u16>,
+    bind: Vec<BindAddr>,
+    identity: Vec<String>,
+    state_dir: Option<PathBuf>,
+    oplist: Option<String>,
+    whitelist: Option<String>,
+    save_keys: Option<bool>,
+    accept_any_key: Option<bool>,
+    bans: Option<String>,
+    motd: Option<String>,
+    history: Option<String>,
+    history_store: Option<StoreKind>,
+    history_len: Option<usize>,
+    #[serde(with = "humantime_serde")]
+    idle_away: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    idle_disconnect: Option<Duration>,
+    #[serde(with = "humantime_serde")]
+    inactivity_timeout: Option<Duration>,
+    message_rate: Option<NonZeroU32>,
+    input_max_len: Option<usize>,
+    #[serde(deserialize_with = "deserialize_theme")]
+    theme: Option<Theme>,
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    timestamp: Option<TimestampMode>,
+    input_history: Option<bool>,
+    input_history_exclude: Option<Vec<String>>,
+    memo_max: Option<usize>,
+    #[serde(with = "humantime_serde")]
+    memo_expiry: Option<Duration>,
+    log: Option<String>,
+}
+
+/// Server settings resolved from the command line, the config file and the
+/// defaults, in that order of precedence.
+pub struct Config {
+    pub listen: Vec<SocketAddr>,
+    pub identity: Vec<String>,
+    pub state_dir: Option<PathBuf>,
+    pub oplist: Option<String>,
+    pub whitelist: Option<String>,
+    pub save_keys: bool,
+    /// Whether clients may authenticate with any key while the whitelist is off
+    pub accept_any_key: bool,
+    pub bans: Option<String>,
+    pub motd: Option<String>,
+    pub history: Option<String>,
+    pub history_store: StoreKind,
+    /// Directory to keep the command history of users with a public key in
+    pub input_history: Option<PathBuf>,
+    pub input_history_exclude: Vec<String>,
+    /// Directory to keep the messages left for users with a public key in
+    pub memos: Option<PathBuf>,
+    pub memo_max: usize,
+    pub memo_expiry: Duration,
+    /// File to keep the last-seen records of the users who left in
+    pub seen: Option<PathBuf>,
+    /// File to keep the names registered with `/register` in
+    pub names: Option<PathBuf>,
+    pub idle_limits: IdleLimits,
+    pub inactivity_timeout: Option<Duration>,
+    pub room: RoomConfig,
+    pub log: Option<String>,
+    pub debug: u8,
+}
+
+impl Config {
+    pub fn load(cli: Cli) -> Result<Self, anyhow::Error> {
+        let file = match &cli.config {
+            Some(path) => {
+                let contents = utils::fs::read_file_to_string(path)
+                    .with_context(|| format!("Failed to read the config file {}", path))?;
+                toml::from_str::<FileConfig>(&contents)
+                    .with_context(|| format!("Invalid config file {}", path))?
+            }
+            None => FileConfig::default(),
+        };
+
+        let idle_limits = IdleLimits {
+            away_after: cli
+                .idle_away
+                .or(file.idle_away)
+                .or(Some(DEFAULT_IDLE_AWAY))
+                .filter(|d| !d.is_zero()),
+            disconnect_after: cli
+                .idle_disconnect
+                .or(file.idle_disconnect)
+                .filter(|d| !d.is_zero()),
+        };
+
+        // The room disconnects idle members by itself, so the SSH level
+        // timeout is only kept as a fallback when it does not
+        let inactivity_timeout = match file.inactivity_timeout {
+            Some(timeout) => Some(timeout).filter(|d| !d.is_zero()),
+            None if idle_limits.disconnect_after.is_some() => None,
+            None => Some(DEFAULT_INACTIVITY_TIMEOUT),
+        };
+
+        let defaults = RoomConfig::default();
+        let room = RoomConfig {
+            history_len: cli
+                .history_len
+                .or(file.history_len)
+                .unwrap_or(defaults.history_len),
+            message_rate: file.message_rate.unwrap_or(defaults.message_rate),
+            input_max_len: file.input_max_len.unwrap_or(defaults.input_max_len),
+            theme: file.theme.unwrap_or(defaults.theme),
+            timestamp_mode: file.timestamp.unwrap_or(defaults.timestamp_mode),
+        };
+
+        let port = cli.port.or(file.port).unwrap_or(DEFAULT_PORT);
+        let bind = match (cli.bind.is_empty(), file.bind.is_empty()) {
+            (false, _) => cli.bind,
+            (true, false) => file.bind,
+            (true, true) => vec![BindAddr {
+                ip: DEFAULT_BIND,
+                port: None,
+            }],
+        };
+        let listen = bind
+            .iter()
+            .map(|addr| addr.with_default_port(port))
+            .collect();
+
+        let state_dir = cli
+            .state_dir
+            .or(file.state_dir)
+            .or_else(host_keys::default_state_dir);
+        let input_history = match (file.input_history.unwrap_or(false), &state_dir) {
+            (false, _) => None,
+            (true, Some(dir)) => Some(dir.join("input-history")),
+            (true, None) => {
+                anyhow::bail!("input-history needs a state directory, set one with --state-dir")
+            }
+        };
+        let memos = state_dir.as_ref().map(|dir| dir.join("memos"));
+        let seen = state_dir.as_ref().map(|dir| dir.join("seen.jsonl"));
+        let names = state_dir.as_ref().map(|dir| dir.join("names.jsonl"));
+
+        Ok(Self {
+            listen,
+            identity: if cli.identity.is_empty() {
+                file.identity
+            } else {
+                cli.identity
+            },
+            state_dir,
+            oplist: cli.oplist.or(file.oplist),
+            whitelist: cli.whitelist.or(file.whitelist),
+            save_keys: cli.save_keys || file.save_keys.unwrap_or(false),
+            accept_any_key: file.accept_any_key.unwrap_or(false),
+            bans: cli.bans.or(file.bans),
+            motd: cli.motd.or(file.motd),
+            history: cli.history.or(file.history),
+            history_store: cli
+                .history_store
+                .or(file.history_store)
+                .unwrap_or(StoreKind::Jsonl),
+            input_history,
+            input_history_exclude: file.input_history_exclude.unwrap_or_else(|| {
+                DEFAULT_INPUT_HISTORY_EXCLUDE
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect()
+            }),
+            memos,
+            memo_max: file.memo_max.unwrap_or(DEFAULT_MEMO_MAX),
+            memo_expiry: file.memo_expiry.unwrap_or(DEFAULT_MEMO_EXPIRY),
+            seen,
+            names,
+            idle_limits,
+            inactivity_timeout,
+            room,
+            log: cli.log.or(file.log),
+            debug: cli.debug,
+        })
+    }
+}
+
+fn deserialize_theme<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Theme>, D::Error> {
+    let expected = format!("one of: {}", Theme::all().join(", "));
+    deserialize_from_str(d, &expected)
+}
+
+fn deserialize_timestamp<'de, D: Deserializer<'de>>(
+    d: D,
+) -> Result<Option<TimestampMode>, D::Error> {
+    deserialize_from_str(d, "one of: time, datetime, off")
+}
+
+fn deserialize_from_str<'de, D, T>(d: D, expected: &str) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = String::deserialize(d)?;
+    T::from_str(&value)
+        .map(Some)
+        .map_err(|_| de::Error::invalid_value(de::Unexpected::Str(&value), &expected))
+}
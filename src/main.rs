@@ -2,11 +2,12 @@ use std::sync::Arc;
 
 use clap::Parser;
 use cli::Cli;
+use config::Config;
 use log::LevelFilter;
-use russh_keys::key::{KeyPair, PublicKey};
 use tokio::sync::Mutex;
 
 mod cli;
+mod config;
 mod logger;
 mod server;
 mod utils;
@@ -14,70 +15,71 @@ mod utils;
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
+    let config = Config::load(cli).expect("Failed to load the configuration");
 
     // Initiate logger
-    let level = match cli.debug {
+    let level = match config.debug {
         0 => LevelFilter::Info,
         1 => LevelFilter::Debug,
         _ => LevelFilter::max(),
     };
-    if let Err(err) = logger::setup(cli.log, level) {
+    if let Err(err) = logger::setup(config.log, level) {
         panic!("Failed to setup logger: {}", err);
     }
 
     // Initiate server keys
-    let key_pair = match cli.identity {
-        None => KeyPair::generate_ed25519().expect("Failed to generate a new ed25519 key pair"),
-        Some(path) => {
-            let key =
-                utils::fs::read_file_to_string(&path).expect("Failed to read the identity file");
-            russh_keys::decode_secret_key(&key, None)
-                .expect("Failed to decode the secret key from the identity file")
-        }
-    };
-    let server_keys = vec![key_pair];
-
-    // Initiate server oplist
-    let oplist = match cli.oplist {
-        Some(path) => Some(
-            utils::fs::read_file_lines(&path)
-                .expect("Failed to read the oplist file")
-                .iter()
-                .filter_map(|line| utils::ssh::split_ssh_key(line))
-                .filter_map(|(_, key, _)| russh_keys::parse_public_key_base64(&key).ok())
-                .collect::<Vec<PublicKey>>(),
-        ),
-        None => None,
-    };
-
-    // Initiate server whitelist
-    let whitelist = match cli.whitelist {
-        Some(path) => Some(
-            utils::fs::read_file_lines(&path)
-                .expect("Failed to read the whitelist file")
-                .iter()
-                .filter_map(|line| utils::ssh::split_ssh_key(line))
-                .filter_map(|(_, key, _)| russh_keys::parse_public_key_base64(&key).ok())
-                .collect::<Vec<PublicKey>>(),
-        ),
-        None => None,
-    };
+    let server_keys = server::host_keys::load(&config.identity, config.state_dir.as_deref())
+        .expect("Failed to load the server host keys");
 
     // Initiate motd
-    let motd = match cli.motd {
-        Some(path) => utils::fs::read_file_to_string(&path).expect("Failed to read the MOTD file"),
-        None => include_str!("../motd.ans").to_string(),
-    }
-    .replace("\n", "\n\r"); // normalize line endings into \r
+    let motd = server::motd::load(config.motd.as_deref()).expect("Failed to read the MOTD file");
 
     // Initiate server <-> session repository message channel
     let (tx, rx) = tokio::sync::mpsc::channel(1000);
 
     // Initate server and session repository
-    let auth = Arc::new(Mutex::new(server::Auth::new(oplist, whitelist)));
-    let room = server::ServerRoom::new(&motd, auth.clone());
+    let mut auth = server::Auth::new(config.oplist, config.whitelist);
+    auth.set_persist_keys(config.save_keys);
+    auth.set_accept_any_key(config.accept_any_key);
+    auth.load_keys()
+        .expect("Failed to read the oplist or whitelist file");
+    if let Some(path) = config.bans {
+        auth.load_bans(&path).expect("Failed to load the bans file");
+    }
+    let auth = Arc::new(Mutex::new(auth));
+    let mut room = server::ServerRoom::new(&motd, config.motd, config.room, auth.clone());
+    if let Some(path) = config.history {
+        let history_len = room.config().history_len;
+        let store = server::history::open(config.history_store, &path, history_len)
+            .expect("Failed to open the history file");
+        room.set_history_store(store)
+            .expect("Failed to load the chat history");
+    }
+    if let Some(dir) = config.input_history {
+        let store = server::history::InputHistoryStore::new(dir, config.input_history_exclude);
+        room.set_input_history_store(store);
+    }
+    if let Some(dir) = config.memos {
+        let store = server::memo::MemoStore::open(dir, config.memo_max, config.memo_expiry)
+            .expect("Failed to read the memos");
+        room.set_memo_store(store);
+    }
+    if let Some(path) = config.seen {
+        let store = server::seen::SeenStore::open(path).expect("Failed to load the last-seen file");
+        room.set_seen_store(store);
+    }
+    if let Some(path) = config.names {
+        let registry =
+            server::names::NameRegistry::open(path).expect("Failed to load the registered names");
+        room.set_name_registry(registry);
+    }
     let repository = server::SessionRepository::new(rx);
-    let mut server = server::AppServer::new(cli.port, auth.clone(), room, &server_keys, tx);
+    let server_config = server::ServerConfig {
+        listen: config.listen,
+        inactivity_timeout: config.inactivity_timeout,
+        idle_limits: config.idle_limits,
+    };
+    let mut server = server::AppServer::new(server_config, auth.clone(), room, &server_keys, tx);
 
     // Run the server
     server.run(repository).await.expect("Failed running server");
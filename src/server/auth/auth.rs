@@ -1,28 +1,96 @@
+use anyhow::Context;
+use chrono::Utc;
+use log::error;
 use russh_keys::key::PublicKey;
+use std::net::IpAddr;
 use std::time::Duration;
 
-use crate::utils::TimedHashSet;
+use super::ban::{self, Attribute as BanAttribute, BanEntry};
+use crate::server::reload::KeyChanges;
+use crate::utils;
 
 #[derive(Clone)]
 pub struct Auth {
     operators: Option<Vec<PublicKey>>,
     trusted_keys: Option<Vec<PublicKey>>,
-    banned_usernames: TimedHashSet<String>,
-    banned_fingerprints: TimedHashSet<String>,
+    oplist_file: Option<String>,
+    whitelist_file: Option<String>,
+    whitelist_enabled: bool,
+    accept_any_key: bool,
+    persist_keys: bool,
+    bans: Vec<BanEntry>,
+    bans_file: Option<String>,
 }
 
 impl Auth {
-    pub fn new(operators: Option<Vec<PublicKey>>, trusted_keys: Option<Vec<PublicKey>>) -> Self {
+    pub fn new(oplist_file: Option<String>, whitelist_file: Option<String>) -> Self {
         Self {
-            operators,
-            trusted_keys,
-            banned_fingerprints: TimedHashSet::new(),
-            banned_usernames: TimedHashSet::new(),
+            operators: None,
+            trusted_keys: None,
+            whitelist_enabled: whitelist_file.is_some(),
+            accept_any_key: false,
+            oplist_file,
+            whitelist_file,
+            persist_keys: false,
+            bans: Vec::new(),
+            bans_file: None,
         }
     }
 
+    /// When enabled, any key is trusted while the whitelist is off
+    pub fn set_accept_any_key(&mut self, accept: bool) {
+        self.accept_any_key = accept;
+    }
+
+    /// When enabled, operator and whitelist changes made at runtime are
+    /// written back to the oplist and whitelist files.
+    pub fn set_persist_keys(&mut self, persist: bool) {
+        self.persist_keys = persist;
+    }
+
+    /// (Re)reads operator and trusted keys from the oplist and whitelist files.
+    /// Nothing is changed if either of the files cannot be read. Keys added at
+    /// runtime are kept if there is no file to read them from.
+    pub fn load_keys(&mut self) -> Result<(KeyChanges, KeyChanges), anyhow::Error> {
+        let operators = match &self.oplist_file {
+            Some(path) => {
+                Some(utils::ssh::read_public_keys(path).context("Failed to read the oplist file")?)
+            }
+            None => self.operators.clone(),
+        };
+
+        let trusted_keys = match &self.whitelist_file {
+            Some(path) => Some(
+                utils::ssh::read_public_keys(path).context("Failed to read the whitelist file")?,
+            ),
+            None => self.trusted_keys.clone(),
+        };
+
+        let changes = (
+            KeyChanges::between(&self.operators, &operators),
+            KeyChanges::between(&self.trusted_keys, &trusted_keys),
+        );
+
+        self.operators = operators;
+        self.trusted_keys = trusted_keys;
+
+        Ok(changes)
+    }
+
+    /// Loads the bans from the given file. Every later change to the bans is
+    /// written back to the same file.
+    pub fn load_bans(&mut self, path: &str) -> Result<(), anyhow::Error> {
+        self.bans = ban::read_bans(path)?;
+        self.bans_file = Some(path.to_string());
+        self.prune_bans();
+        self.save_bans();
+        Ok(())
+    }
+
+    /// Whether the server was started with an oplist. Anonymous clients are
+    /// not let in to such a server.
     pub fn has_operators(&self) -> bool {
-        self.operators.is_some()
+        self.oplist_file.is_some()
     }
 
     pub fn is_op(&self, key: &PublicKey) -> bool {
@@ -32,49 +100,165 @@ impl Auth {
         }
     }
 
+    /// Operators and whitelisted keys are trusted. With `accept_any_key`, so
+    /// is any other key while the whitelist is off: such a key identifies the
+    /// user, e.g. for `/tell` or `/register`, but is not vetted in any way.
     pub fn is_trusted(&self, key: &PublicKey) -> bool {
+        if self.accept_any_key && !self.whitelist_enabled {
+            return true;
+        }
+
+        if self.is_op(key) {
+            return true;
+        }
+
         match &self.trusted_keys {
             Some(list) => list.iter().find(|k| (*k).eq(key)).is_some(),
             None => false,
         }
     }
 
-    pub fn check_bans(&mut self, user: &str, key: &PublicKey) -> bool {
-        let mut is_banned = false;
+    pub fn is_whitelist_enabled(&self) -> bool {
+        self.whitelist_enabled
+    }
+
+    pub fn set_whitelist_enabled(&mut self, enabled: bool) {
+        self.whitelist_enabled = enabled;
+    }
+
+    pub fn trusted_keys(&self) -> &[PublicKey] {
+        self.trusted_keys.as_deref().unwrap_or_default()
+    }
 
-        if !is_banned {
-            is_banned = self.banned_usernames.contains(&user.to_string());
+    /// Grants operator rights to the key. Returns `false` if it already had them.
+    pub fn add_operator(&mut self, key: &PublicKey, comment: &str) -> bool {
+        let added = add_key(&mut self.operators, key);
+        if added {
+            self.append_key_to(&self.oplist_file, key, comment);
         }
+        added
+    }
 
-        if !is_banned {
-            is_banned = self.banned_fingerprints.contains(&key.fingerprint());
+    /// Revokes operator rights from the key. Returns `false` if it had none.
+    pub fn remove_operator(&mut self, key: &PublicKey) -> bool {
+        let removed = remove_key(&mut self.operators, key);
+        if removed {
+            self.remove_key_from(&self.oplist_file, key);
         }
+        removed
+    }
 
-        is_banned
+    /// Adds the key to the whitelist. Returns `false` if it was already there.
+    pub fn add_trusted_key(&mut self, key: &PublicKey, comment: &str) -> bool {
+        let added = add_key(&mut self.trusted_keys, key);
+        if added {
+            self.append_key_to(&self.whitelist_file, key, comment);
+        }
+        added
     }
 
-    pub fn ban_username(&mut self, username: &str, duration: Duration) {
-        self.banned_usernames.insert(username.to_string(), duration)
+    /// Removes the key from the whitelist. Returns `false` if it was not there.
+    pub fn remove_trusted_key(&mut self, key: &PublicKey) -> bool {
+        let removed = remove_key(&mut self.trusted_keys, key);
+        if removed {
+            self.remove_key_from(&self.whitelist_file, key);
+        }
+        removed
     }
 
-    pub fn ban_fingerprint(&mut self, fingerprint: &str, duration: Duration) {
-        self.banned_fingerprints
-            .insert(fingerprint.to_string(), duration)
+    /// Finds an active ban matching any of the given connection attributes.
+    pub fn find_ban(
+        &self,
+        user: &str,
+        key: Option<&PublicKey>,
+        ip: Option<&IpAddr>,
+    ) -> Option<&BanEntry> {
+        let fingerprint = key.map(|k| k.fingerprint());
+        self.active_bans().find(|ban| match &ban.attribute {
+            BanAttribute::Name(name) => name == user,
+            BanAttribute::Fingerprint(f) => Some(f) == fingerprint.as_ref(),
+            BanAttribute::Ip(net) => ip.is_some_and(|ip| net.contains(ip)),
+        })
     }
 
-    pub fn banned(&self) -> (Vec<String>, Vec<String>) {
-        let names = self
-            .banned_usernames
-            .iter()
-            .map(|n| n.into())
-            .collect::<Vec<String>>();
+    pub fn ban(
+        &mut self,
+        attribute: BanAttribute,
+        duration: Duration,
+        banned_by: &str,
+        reason: Option<String>,
+    ) {
+        self.bans.retain(|ban| ban.attribute != attribute);
+        self.bans
+            .push(BanEntry::new(attribute, duration, banned_by, reason));
+        self.prune_bans();
+        self.save_bans();
+    }
+
+    /// Lifts the ban on the given attribute. Returns `false` if there was none.
+    pub fn unban(&mut self, attribute: &BanAttribute) -> bool {
+        let found = self.active_bans().any(|ban| ban.attribute.eq(attribute));
+
+        self.bans.retain(|ban| ban.attribute.ne(attribute));
+        self.prune_bans();
+        self.save_bans();
+
+        found
+    }
+
+    pub fn banned(&self) -> Vec<&BanEntry> {
+        self.active_bans().collect()
+    }
+
+    fn active_bans(&self) -> impl Iterator<Item = &BanEntry> {
+        let now = Utc::now();
+        self.bans.iter().filter(move |ban| ban.expires_at > now)
+    }
+
+    fn prune_bans(&mut self) {
+        self.bans.retain(|ban| !ban.is_expired());
+    }
+
+    fn append_key_to(&self, file: &Option<String>, key: &PublicKey, comment: &str) {
+        if let Some(path) = file.as_ref().filter(|_| self.persist_keys) {
+            if let Err(err) = utils::ssh::append_public_key(path, key, comment) {
+                error!("Failed to add a key to {}: {}", path, err);
+            }
+        }
+    }
+
+    fn remove_key_from(&self, file: &Option<String>, key: &PublicKey) {
+        if let Some(path) = file.as_ref().filter(|_| self.persist_keys) {
+            if let Err(err) = utils::ssh::remove_public_key(path, key) {
+                error!("Failed to remove a key from {}: {}", path, err);
+            }
+        }
+    }
+
+    fn save_bans(&self) {
+        if let Some(path) = &self.bans_file {
+            if let Err(err) = ban::write_bans(path, &self.bans) {
+                error!("Failed to write the bans file {}: {}", path, err);
+            }
+        }
+    }
+}
 
-        let fingerprints = self
-            .banned_fingerprints
-            .iter()
-            .map(|n| n.into())
-            .collect::<Vec<String>>();
+fn add_key(list: &mut Option<Vec<PublicKey>>, key: &PublicKey) -> bool {
+    let list = list.get_or_insert_with(Vec::new);
+    if list.contains(key) {
+        return false;
+    }
+    list.push(key.clone());
+    true
+}
 
-        (names, fingerprints)
+fn remove_key(list: &mut Option<Vec<PublicKey>>, key: &PublicKey) -> bool {
+    match list {
+        Some(list) if list.contains(key) => {
+            list.retain(|k| k.ne(key));
+            true
+        }
+        _ => false,
     }
 }
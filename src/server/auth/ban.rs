@@ -1,5 +1,10 @@
+use chrono::{DateTime, Utc};
 use humantime;
-use std::{str::FromStr, time::Duration};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use std::{fmt, net::IpAddr, str::FromStr, time::Duration};
+
+use crate::utils;
 
 #[derive(Debug)]
 struct BanDuration(Duration);
@@ -16,11 +21,19 @@ impl FromStr for BanDuration {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Attribute {
     Name(String),
     Fingerprint(String),
-    Ip(String),
+    Ip(IpNet),
+}
+
+impl Attribute {
+    /// Keys of the `key=value` form of the ban attributes
+    pub fn keys() -> [&'static str; 3] {
+        ["name", "fingerprint", "ip"]
+    }
 }
 
 impl FromStr for Attribute {
@@ -31,7 +44,13 @@ impl FromStr for Attribute {
             match key {
                 "name" => Ok(Attribute::Name(value.to_string())),
                 "fingerprint" => Ok(Attribute::Fingerprint(value.to_string())),
-                "ip" => Ok(Attribute::Ip(value.to_string())),
+                "ip" => match value.parse::<IpNet>() {
+                    Ok(net) => Ok(Attribute::Ip(net.trunc())),
+                    Err(_) => match value.parse::<IpAddr>() {
+                        Ok(addr) => Ok(Attribute::Ip(IpNet::from(addr))),
+                        Err(_) => Err("invalid ip address or CIDR range"),
+                    },
+                },
                 _ => Err("unknown attribute"),
             }
         } else {
@@ -40,6 +59,16 @@ impl FromStr for Attribute {
     }
 }
 
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Attribute::Name(name) => write!(f, "name={}", name),
+            Attribute::Fingerprint(fingerprint) => write!(f, "fingerprint={}", fingerprint),
+            Attribute::Ip(net) => write!(f, "ip={}", net),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct BanItem {
     pub attribute: Attribute,
@@ -48,14 +77,22 @@ pub struct BanItem {
 
 #[derive(Debug)]
 pub enum BanQuery {
-    Single { name: String, duration: Duration },
-    Multiple(Vec<BanItem>),
+    Single {
+        name: String,
+        duration: Duration,
+        reason: Option<String>,
+    },
+    Multiple {
+        items: Vec<BanItem>,
+        reason: Option<String>,
+    },
 }
 
 impl FromStr for BanQuery {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (s, reason) = split_reason(s)?;
         let mut parts = s.split_whitespace();
 
         // Peek the next part to decide the type of command
@@ -69,6 +106,7 @@ impl FromStr for BanQuery {
             return Ok(BanQuery::Single {
                 name,
                 duration: duration.0,
+                reason,
             });
         }
 
@@ -84,6 +122,92 @@ impl FromStr for BanQuery {
             });
         }
 
-        Ok(BanQuery::Multiple(ban_items))
+        Ok(BanQuery::Multiple {
+            items: ban_items,
+            reason,
+        })
+    }
+}
+
+/// Splits off an optional trailing quoted reason, e.g. `alice 1d "spamming links"`.
+fn split_reason(s: &str) -> Result<(&str, Option<String>), &'static str> {
+    let start = match s.find('"') {
+        Some(start) => start,
+        None => return Ok((s, None)),
+    };
+
+    let rest = &s[start + 1..];
+    let end = rest
+        .rfind('"')
+        .ok_or("reason must be enclosed in double quotes")?;
+    if !rest[end + 1..].trim().is_empty() {
+        return Err("reason must be the last argument");
+    }
+
+    let reason = rest[..end].trim();
+    match reason.is_empty() {
+        true => Ok((&s[..start], None)),
+        false => Ok((&s[..start], Some(reason.to_string()))),
+    }
+}
+
+/// A single ban condition as stored in the bans file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BanEntry {
+    pub attribute: Attribute,
+    pub expires_at: DateTime<Utc>,
+    pub banned_by: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl BanEntry {
+    pub fn new(
+        attribute: Attribute,
+        duration: Duration,
+        banned_by: &str,
+        reason: Option<String>,
+    ) -> Self {
+        let expires_at = chrono::Duration::from_std(duration)
+            .ok()
+            .and_then(|d| Utc::now().checked_add_signed(d))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+
+        Self {
+            attribute,
+            expires_at,
+            banned_by: banned_by.to_string(),
+            reason,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+
+    pub fn remaining(&self) -> Duration {
+        let secs = self
+            .expires_at
+            .signed_duration_since(Utc::now())
+            .num_seconds()
+            .max(0) as u64;
+        Duration::from_secs(secs)
+    }
+}
+
+/// Reads ban entries from a JSON file. A missing or empty file yields no bans.
+pub fn read_bans(path: &str) -> Result<Vec<BanEntry>, anyhow::Error> {
+    match utils::fs::read_file_to_string(path) {
+        Ok(contents) if contents.trim().is_empty() => Ok(vec![]),
+        Ok(contents) => Ok(serde_json::from_str(&contents)?),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(err.into()),
     }
 }
+
+/// Replaces the contents of the bans file with the given entries.
+pub fn write_bans(path: &str, bans: &[BanEntry]) -> Result<(), anyhow::Error> {
+    let contents = serde_json::to_string_pretty(bans)?;
+    utils::fs::write_file_atomically(path, &contents)?;
+    Ok(())
+}
@@ -2,4 +2,4 @@ mod auth;
 mod ban;
 
 pub use auth::Auth;
-pub use ban::{Attribute as BanAttribute, BanQuery};
+pub use ban::{Attribute as BanAttribute, BanEntry, BanQuery};
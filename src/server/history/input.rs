@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use log::warn;
+
+use crate::server::terminal::MAX_HISTORY_SIZE;
+use crate::utils;
+
+/// Commands never written to the input history unless configured otherwise,
+/// as they carry private messages
+pub const DEFAULT_INPUT_HISTORY_EXCLUDE: [&str; 3] = ["msg", "reply", "tell"];
+
+/// Command history of users with a public key, one file of JSON encoded
+/// inputs per key fingerprint. Only the most recent inputs are kept. The
+/// history of a key is read when its first session joins and kept in memory
+/// until its last one leaves, the file is only written when a session leaves.
+pub struct InputHistoryStore {
+    dir: PathBuf,
+    exclude: Vec<String>,
+    inputs: HashMap<String, Vec<String>>,
+}
+
+impl InputHistoryStore {
+    /// Inputs of the commands listed in `exclude`, e.g. `msg`, are never written.
+    pub fn new(dir: PathBuf, exclude: Vec<String>) -> Self {
+        let exclude = exclude
+            .iter()
+            .map(|name| format!("/{}", name.trim_start_matches('/')))
+            .collect();
+        Self {
+            dir,
+            exclude,
+            inputs: HashMap::new(),
+        }
+    }
+
+    /// Returns the inputs made with the given key, oldest first.
+    pub fn load(&mut self, fingerprint: &str) -> Result<Vec<String>, anyhow::Error> {
+        if let Some(inputs) = self.inputs.get(fingerprint) {
+            return Ok(inputs.clone());
+        }
+
+        let inputs = self.read(fingerprint)?;
+        self.inputs.insert(fingerprint.to_string(), inputs.clone());
+        Ok(inputs)
+    }
+
+    /// Adds an input to the history of the given key, dropping the oldest
+    /// ones past the limit. It is written once `save` is called.
+    pub fn append(&mut self, fingerprint: &str, input: &str) -> Result<(), anyhow::Error> {
+        if self.is_excluded(input) {
+            return Ok(());
+        }
+
+        if !self.inputs.contains_key(fingerprint) {
+            self.load(fingerprint)?;
+        }
+        let inputs = self.inputs.get_mut(fingerprint).unwrap();
+        inputs.push(input.to_string());
+        let skip = inputs.len().saturating_sub(MAX_HISTORY_SIZE);
+        inputs.drain(..skip);
+        Ok(())
+    }
+
+    /// Writes the history of the given key, if it was loaded. It is dropped
+    /// from memory unless `keep` is set, e.g. while other sessions of the
+    /// same key are still connected.
+    pub fn save(&mut self, fingerprint: &str, keep: bool) -> Result<(), anyhow::Error> {
+        let inputs = match keep {
+            true => self.inputs.get(fingerprint).cloned(),
+            false => self.inputs.remove(fingerprint),
+        };
+        let Some(inputs) = inputs else {
+            return Ok(());
+        };
+
+        let mut contents = String::new();
+        for input in inputs.iter() {
+            contents.push_str(&serde_json::to_string(input)?);
+            contents.push('\n');
+        }
+
+        let path = self.path(fingerprint);
+        utils::fs::create_private_dir(&self.dir)
+            .and_then(|_| utils::fs::write_file_atomically(&path.to_string_lossy(), &contents))
+            .with_context(|| format!("Failed to write the input history {}", path.display()))
+    }
+
+    fn read(&self, fingerprint: &str) -> Result<Vec<String>, anyhow::Error> {
+        let path = self.path(fingerprint);
+        let lines = match utils::fs::read_file_lines(&path.to_string_lossy()) {
+            Ok(lines) => lines,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("Failed to read the input history {}", path.display())
+                })
+            }
+        };
+
+        let mut inputs = vec![];
+        for (idx, line) in lines.iter().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            match serde_json::from_slice::<String>(line) {
+                Ok(input) => inputs.push(input),
+                Err(err) => warn!(
+                    "Skipped line {} of the input history {}: {}",
+                    idx + 1,
+                    path.display(),
+                    err
+                ),
+            }
+        }
+
+        let skip = inputs.len().saturating_sub(MAX_HISTORY_SIZE);
+        Ok(inputs.split_off(skip))
+    }
+
+    fn is_excluded(&self, input: &str) -> bool {
+        let name = input.split_whitespace().next().unwrap_or_default();
+        self.exclude.iter().any(|excluded| excluded == name)
+    }
+
+    fn path(&self, fingerprint: &str) -> PathBuf {
+        let name = utils::ssh::fingerprint_file_name(fingerprint);
+        self.dir.join(format!("{}.jsonl", name))
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+
+use anyhow::Context;
+use log::warn;
+
+use super::{HistoryRecord, HistoryStore};
+use crate::server::room::message::MessageId;
+use crate::utils;
+
+/// Append-only history file with one JSON encoded message per line. The file
+/// is only read through when it is opened and when a message is edited or
+/// deleted, the most recent messages of every channel are kept in memory.
+pub struct JsonlStore {
+    path: String,
+    tail_len: usize,
+    tails: HashMap<String, VecDeque<HistoryRecord>>,
+    last_id: Option<MessageId>,
+}
+
+impl JsonlStore {
+    /// Reads the file, if any, keeping up to `tail_len` most recent messages
+    /// of every channel.
+    pub fn open(path: &str, tail_len: usize) -> Result<Self, anyhow::Error> {
+        let mut store = Self {
+            path: path.to_string(),
+            tail_len,
+            tails: HashMap::new(),
+            last_id: None,
+        };
+
+        let mut records = vec![];
+        store.read_records(|record| records.push(record))?;
+        for record in records {
+            store.push_tail(record);
+        }
+
+        Ok(store)
+    }
+
+    fn push_tail(&mut self, record: HistoryRecord) {
+        self.last_id = self.last_id.max(record.id);
+
+        let tail = self.tails.entry(record.channel.clone()).or_default();
+        if tail.len() == self.tail_len {
+            tail.pop_front();
+        }
+        tail.push_back(record);
+    }
+
+    /// Calls `f` with every record of the file, oldest first.
+    fn read_records(&self, mut f: impl FnMut(HistoryRecord)) -> Result<(), anyhow::Error> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read the history file {}", self.path))
+            }
+        };
+
+        for (idx, line) in BufReader::new(file).lines().enumerate() {
+            let line =
+                line.with_context(|| format!("Failed to read the history file {}", self.path))?;
+            if line.is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str::<HistoryRecord>(&line) {
+                Ok(record) => f(record),
+                Err(err) => {
+                    // A line may be cut short if the server was killed mid-write
+                    warn!("Skipped line {} of the history file: {}", idx + 1, err);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the whole file with its records changed by `f`, the record
+    /// is dropped if `f` returns `false`. Lines that could not be read are
+    /// dropped as well.
+    fn rewrite_records(
+        &self,
+        mut f: impl FnMut(&mut HistoryRecord) -> bool,
+    ) -> Result<(), anyhow::Error> {
+        let mut contents = String::new();
+        let mut result = Ok(());
+        self.read_records(|mut record| {
+            if result.is_err() || !f(&mut record) {
+                return;
+            }
+            match serde_json::to_string(&record) {
+                Ok(line) => {
+                    contents.push_str(&line);
+                    contents.push('\n');
+                }
+                Err(err) => result = Err(err),
+            }
+        })?;
+        result?;
+
+        utils::fs::write_file_atomically(&self.path, &contents)
+            .with_context(|| format!("Failed to write the history file {}", self.path))
+    }
+}
+
+impl HistoryStore for JsonlStore {
+    fn append(&mut self, record: &HistoryRecord) -> Result<(), anyhow::Error> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open the history file {}", self.path))?;
+        file.write_all(line.as_bytes())?;
+
+        self.push_tail(record.clone());
+        Ok(())
+    }
+
+    fn load(&mut self, channel: &str, limit: usize) -> Result<Vec<HistoryRecord>, anyhow::Error> {
+        let Some(tail) = self.tails.get(channel) else {
+            return Ok(vec![]);
+        };
+
+        let skip = tail.len().saturating_sub(limit);
+        Ok(tail.iter().skip(skip).cloned().collect())
+    }
+
+    fn last_id(&mut self) -> Result<Option<MessageId>, anyhow::Error> {
+        Ok(self.last_id)
+    }
+
+    fn edit(&mut self, id: MessageId, body: &str) -> Result<(), anyhow::Error> {
+        let edit = |record: &mut HistoryRecord| {
+            if record.id == Some(id) {
+                record.body = body.to_string();
+            }
+            record.set_quoted_body(id, body);
+            true
+        };
+
+        self.tails.values_mut().flatten().for_each(|record| {
+            edit(record);
+        });
+        self.rewrite_records(edit)
+    }
+
+    fn delete(&mut self, id: MessageId) -> Result<(), anyhow::Error> {
+        let delete = |record: &mut HistoryRecord| {
+            record.set_quoted_body(id, "");
+            record.id != Some(id)
+        };
+
+        for tail in self.tails.values_mut() {
+            tail.retain_mut(delete);
+        }
+        self.rewrite_records(delete)
+    }
+}
@@ -0,0 +1,42 @@
+mod input;
+mod jsonl;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod store;
+
+pub use input::{InputHistoryStore, DEFAULT_INPUT_HISTORY_EXCLUDE};
+pub use jsonl::JsonlStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+pub use store::{HistoryRecord, HistoryStore};
+
+/// Upper bound for the number of messages requested with `/history`
+pub const HISTORY_MAX_LEN: usize = 500;
+
+/// Backend used to persist the chat history
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    /// Append-only file with one JSON message per line
+    Jsonl,
+    /// SQLite database
+    #[cfg(feature = "sqlite")]
+    Sqlite,
+}
+
+/// Opens the history store of the given kind at the given path. Up to
+/// `history_len` messages of a channel are replayed to users joining it.
+pub fn open(
+    kind: StoreKind,
+    path: &str,
+    history_len: usize,
+) -> Result<Box<dyn HistoryStore>, anyhow::Error> {
+    match kind {
+        StoreKind::Jsonl => Ok(Box::new(JsonlStore::open(
+            path,
+            history_len.max(HISTORY_MAX_LEN),
+        )?)),
+        #[cfg(feature = "sqlite")]
+        StoreKind::Sqlite => Ok(Box::new(SqliteStore::open(path)?)),
+    }
+}
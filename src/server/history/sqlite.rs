@@ -0,0 +1,150 @@
+use std::str::FromStr;
+
+use rusqlite::types::Type;
+use rusqlite::{params, Connection};
+
+use super::store::RecordKind;
+use super::{HistoryRecord, HistoryStore};
+use crate::server::room::message::{MessageId, Quote};
+
+/// History kept in an SQLite database
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self, anyhow::Error> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS messages (
+                id         INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel    TEXT NOT NULL,
+                kind       TEXT NOT NULL,
+                sender     TEXT NOT NULL,
+                body       TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS messages_channel ON messages (channel, id);",
+        )?;
+
+        // Columns added after the table was first created
+        add_column_if_missing(&conn, "message_id", "INTEGER")?;
+        add_column_if_missing(&conn, "reply_to", "TEXT")?;
+        Ok(Self { conn })
+    }
+}
+
+fn add_column_if_missing(conn: &Connection, name: &str, kind: &str) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare("SELECT 1 FROM pragma_table_info('messages') WHERE name = ?1")?;
+    if !stmt.exists(params![name])? {
+        conn.execute_batch(&format!(
+            "ALTER TABLE messages ADD COLUMN {} {}",
+            name, kind
+        ))?;
+    }
+    Ok(())
+}
+
+impl SqliteStore {
+    /// Replaces the quoted body in the replies to the given message
+    fn set_quoted_body(&mut self, id: MessageId, body: &str) -> Result<(), anyhow::Error> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut select =
+                tx.prepare("SELECT id, reply_to FROM messages WHERE reply_to IS NOT NULL")?;
+            let mut update = tx.prepare("UPDATE messages SET reply_to = ?1 WHERE id = ?2")?;
+            let replies = select
+                .query_map([], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<Result<Vec<(i64, String)>, rusqlite::Error>>()?;
+
+            for (row_id, quote) in replies {
+                let mut quote: Quote = serde_json::from_str(&quote)?;
+                if quote.id == id {
+                    quote.set_body(id, body);
+                    update.execute(params![serde_json::to_string(&quote)?, row_id])?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl HistoryStore for SqliteStore {
+    fn append(&mut self, record: &HistoryRecord) -> Result<(), anyhow::Error> {
+        self.conn.execute(
+            "INSERT INTO messages (channel, kind, sender, body, created_at, message_id, reply_to)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                record.channel,
+                record.kind.as_ref(),
+                record.from,
+                record.body,
+                record.created_at,
+                record.id,
+                record
+                    .reply_to
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn load(&mut self, channel: &str, limit: usize) -> Result<Vec<HistoryRecord>, anyhow::Error> {
+        let mut stmt = self.conn.prepare(
+            "SELECT channel, kind, sender, body, created_at, message_id, reply_to FROM messages
+             WHERE channel = ?1 ORDER BY id DESC LIMIT ?2",
+        )?;
+
+        let mut records = stmt
+            .query_map(params![channel, limit as i64], |row| {
+                let kind = row.get::<_, String>(1)?;
+                let reply_to = row.get::<_, Option<String>>(6)?;
+                Ok(HistoryRecord {
+                    id: row.get(5)?,
+                    channel: row.get(0)?,
+                    kind: RecordKind::from_str(&kind).map_err(|err| {
+                        rusqlite::Error::FromSqlConversionFailure(1, Type::Text, Box::new(err))
+                    })?,
+                    from: row.get(2)?,
+                    body: row.get(3)?,
+                    reply_to: reply_to
+                        .map(|quote| serde_json::from_str(&quote))
+                        .transpose()
+                        .map_err(|err| {
+                            rusqlite::Error::FromSqlConversionFailure(6, Type::Text, Box::new(err))
+                        })?,
+                    created_at: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<HistoryRecord>, rusqlite::Error>>()?;
+
+        records.reverse();
+        Ok(records)
+    }
+
+    fn last_id(&mut self) -> Result<Option<MessageId>, anyhow::Error> {
+        let id = self
+            .conn
+            .query_row("SELECT MAX(message_id) FROM messages", [], |row| row.get(0))?;
+        Ok(id)
+    }
+
+    fn edit(&mut self, id: MessageId, body: &str) -> Result<(), anyhow::Error> {
+        self.conn.execute(
+            "UPDATE messages SET body = ?1 WHERE message_id = ?2",
+            params![body, id],
+        )?;
+        self.set_quoted_body(id, body)
+    }
+
+    fn delete(&mut self, id: MessageId) -> Result<(), anyhow::Error> {
+        self.conn
+            .execute("DELETE FROM messages WHERE message_id = ?1", params![id])?;
+        self.set_quoted_body(id, "")
+    }
+}
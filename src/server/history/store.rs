@@ -0,0 +1,114 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::server::room::message::{self, Message, MessageFormatter, MessageId, Quote};
+use crate::server::room::User;
+
+/// Kind of a message that is kept in the history
+#[derive(
+    Debug, Clone, Copy, PartialEq, Serialize, Deserialize, strum::EnumString, strum::AsRefStr,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum RecordKind {
+    Public,
+    Emote,
+    Announce,
+}
+
+/// Message as it is written to a history store
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    // Records written before messages had IDs have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<MessageId>,
+    pub channel: String,
+    pub kind: RecordKind,
+    pub from: String,
+    pub body: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<Quote>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl HistoryRecord {
+    /// Returns `None` for messages that are not kept in the history,
+    /// e.g. private or system messages.
+    pub fn from_message(channel: &str, message: &Message) -> Option<Self> {
+        let (kind, from, body, created_at) = match message {
+            Message::Public(m) => (RecordKind::Public, &m.from, &m.body, m.created_at),
+            Message::Emote(m) => (RecordKind::Emote, &m.from, &m.body, m.created_at),
+            Message::Announce(m) => (RecordKind::Announce, &m.from, &m.body, m.created_at),
+            _ => return None,
+        };
+
+        Some(Self {
+            id: message.get_id(),
+            channel: channel.to_string(),
+            kind,
+            from: from.username.clone(),
+            body: body.clone(),
+            reply_to: message.quote_of_reply(),
+            created_at,
+        })
+    }
+
+    /// Replaces the quoted body if the record replies to the given message
+    pub fn set_quoted_body(&mut self, id: MessageId, body: &str) {
+        if let Some(quote) = &mut self.reply_to {
+            quote.set_body(id, body);
+        }
+    }
+
+    /// Restores the message. Its author is a detached user that only carries
+    /// the name, as they may have left or not be connected at all.
+    pub fn into_message(self) -> Message {
+        let from = User::new(0, self.from, String::new(), None, None, false);
+        match self.kind {
+            RecordKind::Public => message::Public {
+                id: self.id,
+                created_at: self.created_at,
+                from,
+                body: self.body,
+                reply_to: self.reply_to,
+            }
+            .into(),
+            // Only the emotes posted by the server have no ID
+            RecordKind::Emote => message::Emote {
+                id: self.id,
+                created_at: self.created_at,
+                from,
+                body: self.body,
+                system: self.id.is_none(),
+            }
+            .into(),
+            RecordKind::Announce => message::Announce {
+                id: self.id,
+                created_at: self.created_at,
+                from,
+                body: self.body,
+            }
+            .into(),
+        }
+    }
+}
+
+/// Persistent storage of the room messages, so that they survive a restart
+pub trait HistoryStore: Send {
+    /// Adds a message to the end of the history.
+    fn append(&mut self, record: &HistoryRecord) -> Result<(), anyhow::Error>;
+
+    /// Loads up to `limit` most recent messages of a channel, oldest first.
+    fn load(&mut self, channel: &str, limit: usize) -> Result<Vec<HistoryRecord>, anyhow::Error>;
+
+    /// Returns the highest message ID in any channel, so that new messages
+    /// keep getting unique IDs after a restart.
+    fn last_id(&mut self) -> Result<Option<MessageId>, anyhow::Error>;
+
+    /// Replaces the body of a message, along with the quotes of the replies
+    /// to it.
+    fn edit(&mut self, id: MessageId, body: &str) -> Result<(), anyhow::Error>;
+
+    /// Removes a message and empties the quotes of the replies to it.
+    fn delete(&mut self, id: MessageId) -> Result<(), anyhow::Error>;
+}
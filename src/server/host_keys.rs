@@ -0,0 +1,81 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use log::{info, warn};
+use russh_keys::key::KeyPair;
+
+use crate::utils;
+
+/// Name of the generated key inside the state directory
+const GENERATED_KEY_FILE: &str = "host_ed25519_key";
+
+/// Default directory to keep server state in, e.g. `~/.local/share/chatd`
+pub fn default_state_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("chatd"))
+}
+
+/// Loads the server host keys. Without any identity files, an ed25519 key
+/// is generated once and kept in the state directory, so that the server
+/// fingerprint stays the same across restarts.
+pub fn load(paths: &[String], state_dir: Option<&Path>) -> Result<Vec<KeyPair>, anyhow::Error> {
+    if !paths.is_empty() {
+        return paths.iter().map(|path| read_key(path)).collect();
+    }
+
+    let key = match state_dir {
+        Some(dir) => load_or_generate(&dir.join(GENERATED_KEY_FILE))?,
+        None => {
+            warn!("No state directory available, using a temporary host key");
+            generate()?
+        }
+    };
+    Ok(vec![key])
+}
+
+fn read_key(path: &str) -> Result<KeyPair, anyhow::Error> {
+    let key = utils::fs::read_file_to_string(path)
+        .with_context(|| format!("Failed to read the identity file {}", path))?;
+    russh_keys::decode_secret_key(&key, None)
+        .with_context(|| format!("Failed to decode the secret key from {}", path))
+}
+
+fn load_or_generate(path: &Path) -> Result<KeyPair, anyhow::Error> {
+    let path_str = path.to_string_lossy();
+    if path.exists() {
+        info!("Using the host key from {}", path_str);
+        return read_key(&path_str);
+    }
+
+    let key = generate()?;
+    let mut pem = Vec::new();
+    russh_keys::encode_pkcs8_pem(&key, &mut pem)?;
+    write_private(path, &pem)
+        .with_context(|| format!("Failed to write the host key to {}", path_str))?;
+    info!("Generated a new host key in {}", path_str);
+    Ok(key)
+}
+
+fn generate() -> Result<KeyPair, anyhow::Error> {
+    KeyPair::generate_ed25519().context("Failed to generate a new ed25519 key pair")
+}
+
+/// Writes a file only readable by the owner, failing if it already exists
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), std::io::Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
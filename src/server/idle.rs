@@ -0,0 +1,34 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Mutex;
+use tokio::time;
+
+use super::ServerRoom;
+
+/// How often the members are checked for being idle
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long members may stay idle before the server acts on it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdleLimits {
+    /// Mark members as away after this long without input
+    pub away_after: Option<Duration>,
+    /// Disconnect members after this long without input
+    pub disconnect_after: Option<Duration>,
+}
+
+impl IdleLimits {
+    pub fn is_empty(&self) -> bool {
+        self.away_after.is_none() && self.disconnect_after.is_none()
+    }
+}
+
+/// Periodically applies the idle limits to the room members.
+pub async fn watch(room: Arc<Mutex<ServerRoom>>, limits: IdleLimits) {
+    let mut interval = time::interval(IDLE_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        room.lock().await.check_idle(&limits).await;
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// Default number of messages kept for a single recipient
+pub const DEFAULT_MEMO_MAX: usize = 20;
+/// Default time after which undelivered messages are dropped
+pub const DEFAULT_MEMO_EXPIRY: Duration = Duration::from_secs(30 * 24 * 3600);
+/// Number of undelivered messages a single sender may have, to all recipients
+const MEMO_MAX_PER_SENDER: usize = 10;
+
+/// Message left with `/tell` for a user who is not connected
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Memo {
+    pub from: String,
+    // Key of the sender, as the name alone could be anyone's
+    pub from_fingerprint: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Messages waiting for users with a public key to join, one file of JSON
+/// encoded memos per key fingerprint.
+pub struct MemoStore {
+    dir: PathBuf,
+    max: usize,
+    expiry: Duration,
+    // When the memos waiting to be delivered were left, by key of their sender
+    sent: HashMap<String, Vec<DateTime<Utc>>>,
+}
+
+impl MemoStore {
+    /// Every recipient gets at most `max` memos, each one is kept no longer
+    /// than `expiry`. The memos already waiting in `dir` are read to count
+    /// them against the limit of their senders.
+    pub fn open(dir: PathBuf, max: usize, expiry: Duration) -> Result<Self, anyhow::Error> {
+        let mut store = Self {
+            dir,
+            max,
+            expiry,
+            sent: HashMap::new(),
+        };
+
+        let entries = match fs::read_dir(&store.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(store),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read the memos {}", store.dir.display()))
+            }
+        };
+        for entry in entries {
+            let path = entry?.path();
+            for memo in store.load_file(&path)? {
+                store.count(&memo);
+            }
+        }
+
+        Ok(store)
+    }
+
+    /// Queues a memo for the given key. Fails if the recipient already has
+    /// as many memos waiting as allowed, or the sender has sent too many.
+    pub fn push(&mut self, fingerprint: &str, memo: Memo) -> Result<(), anyhow::Error> {
+        self.prune();
+
+        let expired_at = self.expired_at()?;
+        let sent = self.sent.entry(memo.from_fingerprint.clone()).or_default();
+        sent.retain(|created_at| *created_at > expired_at);
+        if sent.len() >= MEMO_MAX_PER_SENDER {
+            anyhow::bail!(
+                "you already have {} messages waiting to be delivered",
+                sent.len()
+            );
+        }
+
+        let mut memos = self.load(fingerprint)?;
+        if memos.len() >= self.max {
+            anyhow::bail!("the recipient already has {} messages waiting", memos.len());
+        }
+        self.count(&memo);
+        memos.push(memo);
+
+        let mut contents = String::new();
+        for memo in memos.iter() {
+            contents.push_str(&serde_json::to_string(memo)?);
+            contents.push('\n');
+        }
+
+        let path = self.path(fingerprint);
+        utils::fs::create_private_dir(&self.dir)
+            .and_then(|_| utils::fs::write_file_atomically(&path.to_string_lossy(), &contents))
+            .with_context(|| format!("Failed to write the memos {}", path.display()))
+    }
+
+    /// Removes and returns the memos left for the given key, oldest first.
+    pub fn take(&mut self, fingerprint: &str) -> Result<Vec<Memo>, anyhow::Error> {
+        let memos = self.load(fingerprint)?;
+        let path = self.path(fingerprint);
+        match fs::remove_file(&path) {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to remove the memos {}", path.display()))
+            }
+        }
+
+        for memo in memos.iter() {
+            if let Some(sent) = self.sent.get_mut(&memo.from_fingerprint) {
+                if let Some(idx) = sent.iter().position(|t| *t == memo.created_at) {
+                    sent.swap_remove(idx);
+                }
+            }
+        }
+        Ok(memos)
+    }
+
+    fn count(&mut self, memo: &Memo) {
+        self.sent
+            .entry(memo.from_fingerprint.clone())
+            .or_default()
+            .push(memo.created_at);
+    }
+
+    /// Removes the files of the recipients whose memos have all expired,
+    /// i.e. the ones last written longer ago than the expiry time.
+    fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let expired = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| {
+                    SystemTime::now()
+                        .duration_since(modified)
+                        .is_ok_and(|age| age > self.expiry)
+                });
+            if !expired {
+                continue;
+            }
+            if let Err(err) = fs::remove_file(entry.path()) {
+                warn!(
+                    "Failed to remove the expired memos {}: {}",
+                    entry.path().display(),
+                    err
+                );
+            }
+        }
+    }
+
+    /// Loads the memos left for the given key that have not expired yet.
+    fn load(&self, fingerprint: &str) -> Result<Vec<Memo>, anyhow::Error> {
+        self.load_file(&self.path(fingerprint))
+    }
+
+    fn load_file(&self, path: &Path) -> Result<Vec<Memo>, anyhow::Error> {
+        let lines = match utils::fs::read_file_lines(&path.to_string_lossy()) {
+            Ok(lines) => lines,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read the memos {}", path.display()))
+            }
+        };
+
+        let expired_at = self.expired_at()?;
+        let mut memos = vec![];
+        for (idx, line) in lines.iter().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            match serde_json::from_slice::<Memo>(line) {
+                Ok(memo) if memo.created_at > expired_at => memos.push(memo),
+                Ok(_) => {}
+                Err(err) => warn!(
+                    "Skipped line {} of the memos {}: {}",
+                    idx + 1,
+                    path.display(),
+                    err
+                ),
+            }
+        }
+        Ok(memos)
+    }
+
+    fn expired_at(&self) -> Result<DateTime<Utc>, anyhow::Error> {
+        Ok(Utc::now() - chrono::Duration::from_std(self.expiry)?)
+    }
+
+    fn path(&self, fingerprint: &str) -> PathBuf {
+        let name = utils::ssh::fingerprint_file_name(fingerprint);
+        self.dir.join(format!("{}.jsonl", name))
+    }
+}
@@ -1,13 +1,23 @@
 mod auth;
 mod env;
+mod idle;
 mod ratelimit;
+mod reload;
 mod room;
 mod server;
 mod session;
 mod session_workflow;
 mod terminal;
 
+pub mod history;
+pub mod host_keys;
+pub mod memo;
+pub mod motd;
+pub mod names;
+pub mod seen;
+
 pub use auth::Auth;
-pub use room::ServerRoom;
-pub use server::AppServer;
+pub use idle::IdleLimits;
+pub use room::{RoomConfig, ServerRoom, Theme, TimestampMode};
+pub use server::{AppServer, ServerConfig};
 pub use session::SessionRepository;
@@ -0,0 +1,11 @@
+const DEFAULT_MOTD: &str = include_str!("../../motd.ans");
+
+/// Reads the message of the day from a file, falling back to the built-in one.
+pub fn load(path: Option<&str>) -> Result<String, std::io::Error> {
+    let motd = match path {
+        Some(path) => crate::utils::fs::read_file_to_string(path)?,
+        None => DEFAULT_MOTD.to_string(),
+    };
+
+    Ok(motd.replace("\n", "\n\r")) // normalize line endings into \r
+}
@@ -0,0 +1,125 @@
+use std::io;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// Name reserved for the users connecting with a given key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Registration {
+    pub name: String,
+    pub fingerprint: String,
+    pub registered_at: DateTime<Utc>,
+}
+
+/// Names registered with `/register`. Names are compared case-insensitively,
+/// so that `Alice` cannot pass for `alice`. They are kept in memory only
+/// unless a file is given.
+#[derive(Default)]
+pub struct NameRegistry {
+    path: Option<PathBuf>,
+    registrations: Vec<Registration>,
+}
+
+impl NameRegistry {
+    /// Loads the registrations from a file of JSON encoded registrations,
+    /// one per line. Every later change is written back to the same file.
+    pub fn open(path: PathBuf) -> Result<Self, anyhow::Error> {
+        let lines = match utils::fs::read_file_lines(&path.to_string_lossy()) {
+            Ok(lines) => lines,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("Failed to read the registered names {}", path.display())
+                })
+            }
+        };
+
+        let mut registrations = vec![];
+        for (idx, line) in lines.iter().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            match serde_json::from_slice::<Registration>(line) {
+                Ok(registration) => registrations.push(registration),
+                Err(err) => warn!(
+                    "Skipped line {} of the registered names {}: {}",
+                    idx + 1,
+                    path.display(),
+                    err
+                ),
+            }
+        }
+
+        Ok(Self {
+            path: Some(path),
+            registrations,
+        })
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Registration> {
+        self.registrations
+            .iter()
+            .find(|r| r.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether the name is free to use for a user with the given key, i.e.
+    /// it is not registered or it is registered to that key.
+    pub fn is_allowed(&self, name: &str, fingerprint: Option<&str>) -> bool {
+        match self.find(name) {
+            Some(registration) => Some(registration.fingerprint.as_str()) == fingerprint,
+            None => true,
+        }
+    }
+
+    /// Reserves the name for the key. The name must not be registered yet.
+    /// A key holds a single name, so that throwaway keys can't reserve every
+    /// common name; the name it held before is released and returned.
+    pub fn register(
+        &mut self,
+        name: &str,
+        fingerprint: &str,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let replaced = self
+            .registrations
+            .iter()
+            .position(|r| r.fingerprint == fingerprint)
+            .map(|idx| self.registrations.remove(idx).name);
+
+        self.registrations.push(Registration {
+            name: name.to_string(),
+            fingerprint: fingerprint.to_string(),
+            registered_at: Utc::now(),
+        });
+        self.save().map(|_| replaced)
+    }
+
+    /// Releases the name. Returns `false` if it was not registered.
+    pub fn unregister(&mut self, name: &str) -> Result<bool, anyhow::Error> {
+        let len = self.registrations.len();
+        self.registrations
+            .retain(|r| !r.name.eq_ignore_ascii_case(name));
+        if self.registrations.len() == len {
+            return Ok(false);
+        }
+        self.save().map(|_| true)
+    }
+
+    fn save(&self) -> Result<(), anyhow::Error> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let mut contents = String::new();
+        for registration in self.registrations.iter() {
+            contents.push_str(&serde_json::to_string(registration)?);
+            contents.push('\n');
+        }
+        utils::fs::write_file_atomically(&path.to_string_lossy(), &contents)
+            .with_context(|| format!("Failed to write the registered names {}", path.display()))
+    }
+}
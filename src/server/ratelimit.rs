@@ -1,26 +1,26 @@
+use std::hash::Hash;
 use std::time::Duration;
 
-use governor::clock::{Clock, QuantaClock, Reference};
-use governor::RateLimiter;
+use governor::clock::{Clock, QuantaClock, QuantaInstant, Reference};
+use governor::state::keyed::DefaultKeyedStateStore;
+use governor::{NotUntil, RateLimiter};
 
-pub type RateLimit = RateLimiter<
-    governor::state::NotKeyed,
-    governor::state::InMemoryState,
+/// Rate limit shared by everything with the same key, e.g. a key fingerprint
+/// or a channel and a member
+pub type KeyedRateLimit<K = String> = RateLimiter<
+    K,
+    DefaultKeyedStateStore<K>,
     governor::clock::DefaultClock,
     governor::middleware::NoOpMiddleware,
 >;
 
-pub fn check(rl: &RateLimit) -> Result<(), Duration> {
-    let err = rl.check().err();
+pub fn check_key<K: Clone + Eq + Hash>(rl: &KeyedRateLimit<K>, key: &K) -> Result<(), Duration> {
+    rl.check_key(key).map_err(remaining)
+}
 
-    match err {
-        Some(nu) => {
-            let now = QuantaClock::default().now();
-            let remaining_nanos = nu.earliest_possible().duration_since(now);
-            let remaining_duration = Duration::from_nanos(remaining_nanos.as_u64());
-            let truncated_remaining_duration = Duration::new(remaining_duration.as_secs(), 0);
-            Err(truncated_remaining_duration)
-        }
-        None => Ok(()),
-    }
+fn remaining(nu: NotUntil<QuantaInstant>) -> Duration {
+    let now = QuantaClock::default().now();
+    let remaining_nanos = nu.earliest_possible().duration_since(now);
+    let remaining_duration = Duration::from_nanos(remaining_nanos.as_u64());
+    Duration::new(remaining_duration.as_secs(), 0)
 }
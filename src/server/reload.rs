@@ -0,0 +1,70 @@
+use std::fmt;
+
+use russh_keys::key::PublicKey;
+
+/// Number of keys added to and removed from a key list by a reload
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct KeyChanges {
+    pub added: usize,
+    pub removed: usize,
+}
+
+impl KeyChanges {
+    pub fn between(old: &Option<Vec<PublicKey>>, new: &Option<Vec<PublicKey>>) -> Self {
+        let empty = vec![];
+        let old = old.as_ref().unwrap_or(&empty);
+        let new = new.as_ref().unwrap_or(&empty);
+        Self {
+            added: new.iter().filter(|k| !old.contains(k)).count(),
+            removed: old.iter().filter(|k| !new.contains(k)).count(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added == 0 && self.removed == 0
+    }
+}
+
+impl fmt::Display for KeyChanges {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.is_empty() {
+            true => write!(f, "unchanged"),
+            false => write!(f, "{} added, {} removed", self.added, self.removed),
+        }
+    }
+}
+
+/// What changed after re-reading the oplist, whitelist and motd files
+#[derive(Debug, Default)]
+pub struct ReloadSummary {
+    pub operators: KeyChanges,
+    pub trusted_keys: KeyChanges,
+    pub motd_changed: bool,
+    pub granted_op: Vec<String>,
+    pub revoked_op: Vec<String>,
+}
+
+impl fmt::Display for ReloadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "oplist: {}; whitelist: {}; motd: {}",
+            self.operators,
+            self.trusted_keys,
+            match self.motd_changed {
+                true => "changed",
+                false => "unchanged",
+            }
+        )?;
+
+        if !self.granted_op.is_empty() {
+            write!(f, "; now operators: {}", self.granted_op.join(", "))?;
+        }
+
+        if !self.revoked_op.is_empty() {
+            write!(f, "; no longer operators: {}", self.revoked_op.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
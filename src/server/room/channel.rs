@@ -0,0 +1,109 @@
+use std::collections::BTreeSet;
+
+use super::message::Message;
+use super::message_history::MessageHistory;
+
+type UserId = usize;
+
+pub const DEFAULT_CHANNEL_NAME: &str = "#lobby";
+
+const CHANNEL_NAME_MAX_LEN: usize = 32;
+
+/// Named chat room within the server. Each channel keeps its own members,
+/// message history, message of the day and topic.
+pub struct Channel {
+    name: String,
+    motd: String,
+    topic: String,
+    members: BTreeSet<UserId>,
+    history: MessageHistory,
+}
+
+impl Channel {
+    pub fn new(name: &str, motd: &str, history_len: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            motd: motd.to_string(),
+            topic: String::new(),
+            members: BTreeSet::new(),
+            history: MessageHistory::new(history_len),
+        }
+    }
+
+    /// Normalizes a user supplied channel name, e.g. `ops` becomes `#ops`.
+    pub fn parse_name(name: &str) -> Result<String, String> {
+        let name = name.strip_prefix('#').unwrap_or(name).to_lowercase();
+
+        if name.is_empty() {
+            return Err("room name must not be empty".to_string());
+        }
+
+        if name.len() > CHANNEL_NAME_MAX_LEN {
+            return Err(format!(
+                "room name must be at most {} characters long",
+                CHANNEL_NAME_MAX_LEN
+            ));
+        }
+
+        let is_valid_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if !name.chars().all(is_valid_char) {
+            return Err(
+                "room name may only contain latin letters, digits, '-' and '_'".to_string(),
+            );
+        }
+
+        Ok(format!("#{}", name))
+    }
+
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_CHANNEL_NAME
+    }
+
+    pub fn motd(&self) -> &String {
+        &self.motd
+    }
+
+    pub fn set_motd(&mut self, motd: String) {
+        self.motd = motd;
+    }
+
+    pub fn topic(&self) -> &String {
+        &self.topic
+    }
+
+    pub fn set_topic(&mut self, topic: String) {
+        self.topic = topic;
+    }
+
+    pub fn members(&self) -> &BTreeSet<UserId> {
+        &self.members
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn add_member(&mut self, user_id: UserId) {
+        self.members.insert(user_id);
+    }
+
+    pub fn remove_member(&mut self, user_id: &UserId) {
+        self.members.remove(user_id);
+    }
+
+    pub fn history(&self) -> &MessageHistory {
+        &self.history
+    }
+
+    pub fn history_mut(&mut self) -> &mut MessageHistory {
+        &mut self.history
+    }
+
+    pub fn push_history(&mut self, message: Message) {
+        self.history.push(message);
+    }
+}
@@ -1,4 +1,7 @@
+use super::channel::Channel;
+use super::message::MessageId;
 use super::user::{Theme, TimestampMode};
+use crate::server::history::HISTORY_MAX_LEN;
 use crate::utils;
 
 use fmt::Write;
@@ -23,6 +26,16 @@ pub enum Command {
     #[strum(props(Cmd = "/name", Args = "<name>", Help = "Rename yourself"))]
     Name(String),
 
+    #[strum(props(Cmd = "/register", Help = "Reserve your name for your public key"))]
+    Register,
+
+    #[strum(props(
+        Cmd = "/unregister",
+        Args = "[name]",
+        Help = "Release your registered name or the given one"
+    ))]
+    Unregister(Option<String>),
+
     #[strum(props(
         Cmd = "/msg",
         Args = "<user> <message>",
@@ -37,6 +50,13 @@ pub enum Command {
     ))]
     Reply(String),
 
+    #[strum(props(
+        Cmd = "/tell",
+        Args = "<user|fingerprint> <message>",
+        Help = "Leave a message for a user with a key to get when they next join"
+    ))]
+    Tell(String, String),
+
     #[strum(props(Cmd = "/ignore", Args = "[user]", Help = "Hide messages from a user"))]
     Ignore(Option<String>),
 
@@ -60,6 +80,13 @@ pub enum Command {
     #[strum(props(Cmd = "/whois", Args = "<user>", Help = "Information about a user"))]
     Whois(String),
 
+    #[strum(props(
+        Cmd = "/seen",
+        Args = "<user>",
+        Help = "Show when a user was last seen"
+    ))]
+    Seen(String),
+
     #[strum(props(
         Cmd = "/timestamp",
         Args = "<time|datetime>",
@@ -76,6 +103,64 @@ pub enum Command {
     #[strum(props(Cmd = "/quiet", Help = "Silence room announcements"))]
     Quiet,
 
+    #[strum(props(Cmd = "/ids", Help = "Prefix messages with their IDs"))]
+    Ids,
+
+    #[strum(props(
+        Cmd = "/join",
+        Args = "<room>",
+        Help = "Join a room, creating it if it does not exist"
+    ))]
+    Join(String),
+
+    #[strum(props(Cmd = "/part", Help = "Leave the current room and return to the lobby"))]
+    Part,
+
+    #[strum(props(Cmd = "/rooms", Help = "List available rooms"))]
+    Rooms,
+
+    #[strum(props(
+        Cmd = "/topic",
+        Args = "[text]",
+        Help = "Set the topic of the current room, or print it if no text"
+    ))]
+    Topic(Option<String>),
+
+    #[strum(props(
+        Cmd = "/history",
+        Args = "[n]",
+        Help = "Show the last n messages of the room"
+    ))]
+    History(Option<usize>),
+
+    #[strum(props(
+        Cmd = "/re",
+        Args = "<id> <message>",
+        Help = "Reply to a message of the room by its ID"
+    ))]
+    Re(MessageId, String),
+
+    #[strum(props(
+        Cmd = "/thread",
+        Args = "<id>",
+        Help = "Show a message along with all the replies to it"
+    ))]
+    Thread(MessageId),
+
+    #[strum(props(
+        Cmd = "/edit",
+        Args = "<id|last> <message>",
+        Help = "Edit one of your recent messages"
+    ))]
+    Edit(MessageRef, String),
+
+    #[strum(props(
+        Cmd = "/delete",
+        Args = "<id|last>",
+        Help = "Delete one of your recent messages"
+    ))]
+    Delete(MessageRef),
+
     /// Operator commands
 
     #[strum(props(
@@ -102,6 +187,14 @@ pub enum Command {
     ))]
     Ban(String),
 
+    #[strum(props(
+        Cmd = "/unban",
+        Args = "<query>",
+        Help = "Lift a ban by name, fingerprint or ip",
+        Op = "true"
+    ))]
+    Unban(String),
+
     #[strum(props(Cmd = "/banned", Help = "List the current ban conditions", Op = "true"))]
     Banned,
 
@@ -113,6 +206,37 @@ pub enum Command {
     ))]
     Motd(Option<String>),
 
+    #[strum(props(
+        Cmd = "/op",
+        Args = "<user>",
+        Help = "Grant operator rights to a user",
+        Op = "true"
+    ))]
+    Op(String),
+
+    #[strum(props(
+        Cmd = "/deop",
+        Args = "<user>",
+        Help = "Revoke operator rights from a user",
+        Op = "true"
+    ))]
+    Deop(String),
+
+    #[strum(props(
+        Cmd = "/whitelist",
+        Args = "<action> [arg]",
+        Help = "Manage the whitelist: add, remove, list, on, off, import",
+        Op = "true"
+    ))]
+    Whitelist(WhitelistAction),
+
+    #[strum(props(
+        Cmd = "/reload",
+        Help = "Reload the oplist, whitelist and motd files",
+        Op = "true"
+    ))]
+    Reload,
+
     /// Secret commands (just hidden or easter eggs)
 
     #[strum(props(Cmd = "/me", Args = "[action]"))]
@@ -134,6 +258,28 @@ pub enum Command {
     Uptime,
 }
 
+/// Subcommands of `/whitelist`. `Add` and `Remove` take a user name or a
+/// public key, `Remove` also accepts a key fingerprint.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum WhitelistAction {
+    #[default]
+    List,
+    Add(String),
+    Remove(String),
+    On,
+    Off,
+    Import(String),
+}
+
+/// Message of the room referred to by its ID, or the most recent message
+/// the user sent
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MessageRef {
+    Id(MessageId),
+    #[default]
+    Last,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum CommandParseError {
     NotRecognizedAsCommand,
@@ -178,6 +324,12 @@ impl FromStr for Command {
                 Some(new_name) => Ok(Command::Name(new_name.to_string())),
                 None => Err(Self::Err::ArgumentExpected(format!("new name"))),
             },
+            b"/register" => Ok(Command::Register),
+            b"/unregister" => match args.split(' ').next() {
+                Some("") => Ok(Command::Unregister(None)),
+                Some(name) => Ok(Command::Unregister(Some(name.to_string()))),
+                None => unreachable!(), // split returns [""] for an empty input
+            },
             b"/msg" => {
                 let mut iter = args.splitn(2, ' ');
                 let user = iter.next();
@@ -199,6 +351,16 @@ impl FromStr for Command {
                 };
                 Ok(Command::Reply(args.to_string()))
             }
+            b"/tell" => {
+                let (to, body) = args.split_once(' ').unwrap_or((args, ""));
+                if to.is_empty() {
+                    return Err(Self::Err::ArgumentExpected("user name".to_string()));
+                }
+                if body.trim().is_empty() {
+                    return Err(Self::Err::ArgumentExpected("message body".to_string()));
+                }
+                Ok(Command::Tell(to.to_string(), body.trim_start().to_string()))
+            }
             b"/users" => Ok(Command::Users),
             b"/whois" => match args.splitn(2, ' ').nth(0) {
                 Some(user) if user.is_empty() => {
@@ -214,10 +376,16 @@ impl FromStr for Command {
             },
             b"/shrug" => Ok(Command::Shrug),
             b"/quiet" => Ok(Command::Quiet),
+            b"/ids" => Ok(Command::Ids),
             b"/me" => match args.is_empty() {
                 true => Ok(Command::Me(None)),
                 false => Ok(Command::Me(Some(args.to_string()))),
             },
+            b"/seen" => match args.split(' ').next() {
+                Some("") => Err(Self::Err::ArgumentExpected("user name".to_string())),
+                Some(user) => Ok(Command::Seen(user.to_string())),
+                None => unreachable!(), // split returns [""] for an empty input
+            },
             b"/timestamp" => match args.splitn(2, ' ').nth(0) {
                 Some(mode) if mode.is_empty() => Err(Self::Err::Custom(
                     "timestamp value must be one of: time, datetime, off".to_string(),
@@ -268,6 +436,55 @@ impl FromStr for Command {
                 Some(users) => Ok(Command::Focus(Some(users.to_string()))),
                 None => unreachable!(), // splitn returns [""] for an empty input
             },
+            b"/join" => match args.split(' ').next() {
+                Some("") => Err(Self::Err::ArgumentExpected("room name".to_string())),
+                Some(room) => match Channel::parse_name(room) {
+                    Ok(name) => Ok(Command::Join(name)),
+                    Err(err) => Err(Self::Err::Custom(err)),
+                },
+                None => unreachable!(), // split returns [""] for an empty input
+            },
+            b"/part" => Ok(Command::Part),
+            b"/rooms" => Ok(Command::Rooms),
+            b"/topic" => Ok(match args.is_empty() {
+                true => Command::Topic(None),
+                false => Command::Topic(Some(args.to_string())),
+            }),
+            b"/history" => match args.split(' ').next() {
+                Some("") => Ok(Command::History(None)),
+                Some(n) => match n.parse::<usize>() {
+                    Ok(n) if n > 0 && n <= HISTORY_MAX_LEN => Ok(Command::History(Some(n))),
+                    _ => Err(Self::Err::Custom(format!(
+                        "number of messages must be between 1 and {}",
+                        HISTORY_MAX_LEN
+                    ))),
+                },
+                None => unreachable!(), // split returns [""] for an empty input
+            },
+            b"/re" => {
+                let (id, body) = args.split_once(' ').unwrap_or((args, ""));
+                let id = parse_message_id(id)?;
+                if body.trim().is_empty() {
+                    return Err(Self::Err::ArgumentExpected("message body".to_string()));
+                }
+                Ok(Command::Re(id, body.trim_start().to_string()))
+            }
+            b"/thread" => match args.split(' ').next() {
+                Some(id) => Ok(Command::Thread(parse_message_id(id)?)),
+                None => unreachable!(), // split returns [""] for an empty input
+            },
+            b"/edit" => {
+                let (target, body) = args.split_once(' ').unwrap_or((args, ""));
+                let target = parse_message_ref(target)?;
+                if body.trim().is_empty() {
+                    return Err(Self::Err::ArgumentExpected("message body".to_string()));
+                }
+                Ok(Command::Edit(target, body.trim_start().to_string()))
+            }
+            b"/delete" => match args.split(' ').next() {
+                Some(target) => Ok(Command::Delete(parse_message_ref(target)?)),
+                None => unreachable!(), // split returns [""] for an empty input
+            },
             b"/help" => Ok(Command::Help),
             b"/version" => Ok(Command::Version),
             b"/uptime" => Ok(Command::Uptime),
@@ -295,12 +512,77 @@ impl FromStr for Command {
                 };
                 Ok(Command::Ban(args.to_string()))
             }
+            b"/unban" => {
+                if args.is_empty() {
+                    return Err(Self::Err::ArgumentExpected("unban query".to_string()));
+                };
+                Ok(Command::Unban(args.to_string()))
+            }
             b"/banned" => Ok(Command::Banned),
+            b"/op" => match args.split(' ').next() {
+                Some("") => Err(Self::Err::ArgumentExpected("user name".to_string())),
+                Some(user) => Ok(Command::Op(user.to_string())),
+                None => unreachable!(), // split returns [""] for an empty input
+            },
+            b"/deop" => match args.split(' ').next() {
+                Some("") => Err(Self::Err::ArgumentExpected("user name".to_string())),
+                Some(user) => Ok(Command::Deop(user.to_string())),
+                None => unreachable!(), // split returns [""] for an empty input
+            },
+            b"/whitelist" => {
+                let (action, arg) = match args.split_once(' ') {
+                    Some((action, arg)) => (action, arg.trim()),
+                    None => (args, ""),
+                };
+
+                let action =
+                    match (action, arg.is_empty()) {
+                        ("list", _) => WhitelistAction::List,
+                        ("on", _) => WhitelistAction::On,
+                        ("off", _) => WhitelistAction::Off,
+                        ("add", false) => WhitelistAction::Add(arg.to_string()),
+                        ("remove", false) => WhitelistAction::Remove(arg.to_string()),
+                        ("import", false) => WhitelistAction::Import(arg.to_string()),
+                        ("add" | "remove", true) => {
+                            return Err(Self::Err::ArgumentExpected(
+                                "user name or public key".to_string(),
+                            ))
+                        }
+                        ("import", true) => {
+                            return Err(Self::Err::ArgumentExpected("file path".to_string()))
+                        }
+                        _ => return Err(Self::Err::Custom(
+                            "whitelist action must be one of: add, remove, list, on, off, import"
+                                .to_string(),
+                        )),
+                    };
+                Ok(Command::Whitelist(action))
+            }
+            b"/reload" => Ok(Command::Reload),
             _ => Err(Self::Err::UnknownCommand),
         }
     }
 }
 
+/// Parses a message ID as shown in front of messages, e.g. `#12` or `12`
+fn parse_message_id(s: &str) -> Result<MessageId, CommandParseError> {
+    if s.is_empty() {
+        return Err(CommandParseError::ArgumentExpected(
+            "message id".to_string(),
+        ));
+    }
+    s.trim_start_matches('#')
+        .parse::<MessageId>()
+        .map_err(|_| CommandParseError::Custom(format!("invalid message id: {}", s)))
+}
+
+fn parse_message_ref(s: &str) -> Result<MessageRef, CommandParseError> {
+    match s {
+        "last" => Ok(MessageRef::Last),
+        _ => parse_message_id(s).map(MessageRef::Id),
+    }
+}
+
 impl Command {
     pub fn cmd(&self) -> &str {
         self.get_str("Cmd").unwrap_or_default()
@@ -356,13 +638,11 @@ impl CommandCollection {
         result
     }
 
-    pub fn from_prefix(&self, prefix: &str) -> Option<&Command> {
-        for cmd in &self.commands {
-            if cmd.cmd().starts_with(prefix) {
-                return Some(cmd);
-            }
-        }
-        None
+    pub fn from_prefix(&self, prefix: &str) -> Vec<&Command> {
+        self.commands
+            .iter()
+            .filter(|cmd| cmd.cmd().starts_with(prefix))
+            .collect()
     }
 
     fn format<'a, I>(&self, commands: I, count: usize) -> String
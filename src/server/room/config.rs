@@ -0,0 +1,34 @@
+use std::num::NonZeroU32;
+
+use nonzero_ext::nonzero;
+
+use super::user::{Theme, TimestampMode};
+
+/// Default number of messages replayed to a member joining a channel
+pub const DEFAULT_HISTORY_LEN: usize = 20;
+/// Default number of messages a member may send per second
+pub const DEFAULT_MESSAGE_RATE: NonZeroU32 = nonzero!(10u32);
+/// Default maximum length of a single input line
+pub const DEFAULT_INPUT_MAX_LEN: usize = 1024;
+
+/// Settings shared by all channels of a server room
+#[derive(Debug, Clone)]
+pub struct RoomConfig {
+    pub history_len: usize,
+    pub message_rate: NonZeroU32,
+    pub input_max_len: usize,
+    pub theme: Theme,
+    pub timestamp_mode: TimestampMode,
+}
+
+impl Default for RoomConfig {
+    fn default() -> Self {
+        Self {
+            history_len: DEFAULT_HISTORY_LEN,
+            message_rate: DEFAULT_MESSAGE_RATE,
+            input_max_len: DEFAULT_INPUT_MAX_LEN,
+            theme: Theme::default(),
+            timestamp_mode: TimestampMode::default(),
+        }
+    }
+}
@@ -1,28 +1,50 @@
+use std::time::Duration;
+
 use chrono::{DateTime, Utc};
 use tokio::sync::mpsc;
 
 use crate::server::room::message;
 use crate::server::room::message::Message;
-use crate::server::room::user::User;
+use crate::server::room::user::{RenderMode, User};
+use crate::utils;
 
 use super::message::MessageFormatter;
 
 #[derive(Clone)]
 pub struct RoomMember {
     pub user: User,
+    channel: String,
     message_tx: mpsc::Sender<String>,
+    disconnect_tx: mpsc::Sender<String>,
     last_sent_at: Option<DateTime<Utc>>,
+    last_active_at: DateTime<Utc>,
 }
 
 impl RoomMember {
-    pub fn new(user: User, message_tx: mpsc::Sender<String>) -> Self {
+    pub fn new(
+        user: User,
+        channel: String,
+        message_tx: mpsc::Sender<String>,
+        disconnect_tx: mpsc::Sender<String>,
+    ) -> Self {
         Self {
             user,
+            channel,
             message_tx,
+            disconnect_tx,
             last_sent_at: None,
+            last_active_at: Utc::now(),
         }
     }
 
+    pub fn channel(&self) -> &String {
+        &self.channel
+    }
+
+    pub fn set_channel(&mut self, channel: String) {
+        self.channel = channel;
+    }
+
     pub fn last_sent_time(&self) -> &Option<DateTime<Utc>> {
         &self.last_sent_at
     }
@@ -31,11 +53,35 @@ impl RoomMember {
         self.last_sent_at = Some(time);
     }
 
+    /// Records the last keystroke of the member, not only a sent message
+    pub fn update_last_active_time(&mut self, time: DateTime<Utc>) {
+        self.last_active_at = time;
+    }
+
+    pub fn idle_duration(&self) -> Duration {
+        let now = Utc::now();
+        let secs = now.signed_duration_since(self.last_active_at).num_seconds() as u64;
+        Duration::from_secs(secs)
+    }
+
+    /// Sends the member's messages to another sender, e.g. the output of a
+    /// command run over exec on their behalf. Returns the previous sender.
+    pub fn replace_message_sender(
+        &mut self,
+        message_tx: mpsc::Sender<String>,
+    ) -> mpsc::Sender<String> {
+        std::mem::replace(&mut self.message_tx, message_tx)
+    }
+
     pub async fn send_message(&self, msg: Message) -> Result<(), mpsc::error::SendError<String>> {
-        let message = match self.user.timestamp_mode.format() {
-            Some(fmt) => msg.format_with_timestamp(&self.user, fmt),
-            None => msg.format(&self.user),
+        let message = match self.user.render_mode {
+            RenderMode::Json => msg.format_json(),
+            RenderMode::Terminal | RenderMode::Plain => match self.user.timestamp_mode.format() {
+                Some(fmt) => msg.format_with_timestamp(&self.user, fmt),
+                None => msg.format_with_id(&self.user),
+            },
         };
+        let message = self.strip_if_plain(message);
         self.message_tx.send(message).await
     }
 
@@ -46,4 +92,28 @@ impl RoomMember {
         );
         self.send_message(msg.into()).await
     }
+
+    /// Closes the member's session. The reason is shown to the member right
+    /// before the disconnect.
+    pub async fn disconnect(&self, reason: String) {
+        let msg: Message = message::System::new(self.user.clone(), reason).into();
+        let msg = match self.user.render_mode {
+            RenderMode::Json => msg.format_json(),
+            RenderMode::Terminal | RenderMode::Plain => msg.format(&self.user),
+        };
+        let msg = self.strip_if_plain(msg);
+        // A full channel means a disconnect is already pending. Waiting for
+        // it to be taken would block the room, which the session needs to
+        // leave it.
+        let _ = self.disconnect_tx.try_send(msg);
+    }
+
+    /// Plain terminals get no escape sequences at all, including the ones
+    /// that come with the message itself, e.g. a colored MOTD.
+    fn strip_if_plain(&self, message: String) -> String {
+        match self.user.render_mode {
+            RenderMode::Plain => utils::strip_ansi(&message),
+            RenderMode::Terminal | RenderMode::Json => message,
+        }
+    }
 }
@@ -1,8 +1,10 @@
 use chrono::{DateTime, Utc};
 use enum_dispatch::enum_dispatch;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
 use super::user::User;
+use crate::utils;
 
 #[enum_dispatch]
 #[derive(Clone)]
@@ -23,37 +25,219 @@ pub enum Message {
     Command,
 }
 
+/// Identifier of a message kept in the history, unique across the channels
+pub type MessageId = u64;
+
+/// Maximum number of characters of the original message shown in a reply
+const QUOTE_MAX_LEN: usize = 50;
+
+/// Message being replied to. It is kept along with the reply, as the
+/// original may have left the history since.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Quote {
+    pub id: MessageId,
+    pub from: String,
+    pub body: String,
+}
+
+impl Quote {
+    /// Replaces the quoted body if it belongs to the given message. The body
+    /// of a deleted message is empty.
+    pub fn set_body(&mut self, id: MessageId, body: &str) {
+        if self.id == id {
+            self.body = body.to_string();
+        }
+    }
+}
+
+/// Message as it is sent to sessions in the JSON render mode
+#[derive(Serialize)]
+struct JsonMessage<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    id: Option<MessageId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<Quote>,
+    from: Option<&'a str>,
+    to: Option<&'a str>,
+    body: String,
+    created_at: DateTime<Utc>,
+}
+
+impl Message {
+    /// Formats the message as a single line JSON object. Server messages,
+    /// e.g. command results, have no author.
+    pub fn format_json(&self) -> String {
+        let (kind, from, to, body, created_at) = match self {
+            Message::Public(m) => ("public", Some(&m.from), None, &m.body, m.created_at),
+            Message::Emote(m) => ("emote", Some(&m.from), None, &m.body, m.created_at),
+            Message::Announce(m) => ("announce", Some(&m.from), None, &m.body, m.created_at),
+            Message::Private(m) => ("private", Some(&m.from), Some(&m.to), &m.body, m.created_at),
+            Message::System(m) => ("system", None, None, &m.body, m.created_at),
+            Message::Error(m) => ("error", None, None, &m.body, m.created_at),
+            Message::Command(m) => ("command", Some(&m.from), None, &m.body, m.created_at),
+        };
+
+        let reply_to = self.quote_of_reply();
+
+        let message = JsonMessage {
+            kind,
+            id: self.get_id(),
+            reply_to,
+            from: from.map(|u| u.username.as_str()),
+            to: to.map(|u| u.username.as_str()),
+            body: utils::strip_ansi(body).replace(utils::NEWLINE, "\n"),
+            created_at,
+        };
+        serde_json::to_string(&message).unwrap()
+    }
+
+    /// Sets the ID of a message kept in the history. Other messages have none.
+    pub fn set_id(&mut self, id: MessageId) {
+        match self {
+            Message::Public(m) => m.id = Some(id),
+            Message::Emote(m) if !m.system => m.id = Some(id),
+            Message::Announce(m) => m.id = Some(id),
+            _ => {}
+        }
+    }
+
+    /// Returns the quote to reply to the message with, if it has an ID
+    pub fn quote(&self) -> Option<Quote> {
+        let (id, from, body) = match self {
+            Message::Public(m) => (m.id?, &m.from, &m.body),
+            Message::Emote(m) => (m.id?, &m.from, &m.body),
+            Message::Announce(m) => (m.id?, &m.from, &m.body),
+            _ => return None,
+        };
+
+        Some(Quote {
+            id,
+            from: from.username.clone(),
+            body: body.clone(),
+        })
+    }
+
+    /// Returns the quote of the message this one replies to
+    pub fn quote_of_reply(&self) -> Option<Quote> {
+        match self {
+            Message::Public(m) => m.reply_to.clone(),
+            _ => None,
+        }
+    }
+
+    /// Returns the author of a message a user sent to a channel, i.e. a
+    /// public message or an emote not posted by the server
+    pub fn sender(&self) -> Option<&User> {
+        match self {
+            Message::Public(m) => Some(&m.from),
+            Message::Emote(m) if !m.system => Some(&m.from),
+            _ => None,
+        }
+    }
+
+    /// Whether the user sent the message, from the same session or with the
+    /// same key. Names alone do not tell, as anyone can take a free name.
+    /// Messages restored from a history store have neither.
+    pub fn is_sent_by(&self, user: &User) -> bool {
+        self.sender().is_some_and(|from| {
+            from.id == user.id || (from.public_key.is_some() && from.public_key == user.public_key)
+        })
+    }
+
+    /// Replaces the body of a message a user sent to a channel. Other
+    /// messages are left as is.
+    pub fn set_body(&mut self, body: &str) {
+        match self {
+            Message::Public(m) => m.body = body.to_string(),
+            Message::Emote(m) => m.body = body.to_string(),
+            _ => {}
+        }
+    }
+
+    /// Replaces the quoted body if the message replies to the given one
+    pub fn set_quoted_body(&mut self, id: MessageId, body: &str) {
+        if let Message::Public(Public {
+            reply_to: Some(quote),
+            ..
+        }) = self
+        {
+            quote.set_body(id, body);
+        }
+    }
+}
+
 /// Trait for formatting a message within the context of a chat user
 #[enum_dispatch(Message)]
 pub trait MessageFormatter: Clone {
     fn format(&self, user: &User) -> String;
     fn get_created_at(&self) -> DateTime<Utc>;
 
+    fn get_id(&self) -> Option<MessageId> {
+        None
+    }
+
+    /// Formats the message prefixed with its ID, e.g. `#12`, if the user
+    /// chose to see them
+    fn format_with_id(&self, user: &User) -> String {
+        match self.get_id() {
+            Some(id) if user.show_ids => prefix_lines(
+                &user
+                    .theme
+                    .style_system_text(&format!("#{}", id))
+                    .to_string(),
+                self.format(user),
+            ),
+            _ => self.format(user),
+        }
+    }
+
     fn format_with_timestamp(&self, user: &User, format: &str) -> String {
-        let timestamp = self.get_created_at().format(format);
-        format!(
-            "{} {}",
-            user.theme.style_system_text(&timestamp.to_string()),
-            self.format(user)
+        let timestamp = self.get_created_at().format(format).to_string();
+        prefix_lines(
+            &user.theme.style_system_text(&timestamp).to_string(),
+            self.format_with_id(user),
         )
     }
 }
 
+/// Prefixes the first line of a formatted message. Continuation lines are
+/// aligned with the first one, after the prefix.
+fn prefix_lines(prefix: &str, text: String) -> String {
+    let indent = " ".repeat(utils::display_width(&utils::strip_ansi(prefix)) + 1);
+    format!(
+        "{} {}",
+        prefix,
+        text.replace(utils::NEWLINE, &format!("{}{}", utils::NEWLINE, indent))
+    )
+}
+
 #[derive(Clone, Debug)]
 pub struct Public {
+    pub id: Option<MessageId>,
     pub created_at: DateTime<Utc>,
     pub from: User,
     pub body: String,
+    pub reply_to: Option<Quote>,
 }
 
 impl Public {
     pub fn new(from: User, body: String) -> Self {
         Self {
+            id: None,
             from,
             body,
+            reply_to: None,
             created_at: Utc::now(),
         }
     }
+
+    pub fn reply(from: User, body: String, quote: Quote) -> Self {
+        Self {
+            reply_to: Some(quote),
+            ..Self::new(from, body)
+        }
+    }
 }
 
 impl MessageFormatter for Public {
@@ -63,13 +247,45 @@ impl MessageFormatter for Public {
         let replacement = user.theme.style_tagged_username(&pattern).to_string();
         let message = re.replace_all(&self.body, replacement).to_string();
 
+        // Continuation lines of a multi-line message are aligned with the
+        // first one, after the author name
+        let indent = " ".repeat(utils::display_width(&self.from.username) + 2);
+        let message = message.replace('\n', &format!("{}{}", utils::NEWLINE, indent));
+
         let username = user.theme.style_username(&self.from.username);
-        format!("{}: {}", username, message)
+        match &self.reply_to {
+            Some(quote) => format!(
+                "{}: {}{}{}{}",
+                username,
+                user.theme.style_system_text(&format_quote(quote)),
+                utils::NEWLINE,
+                indent,
+                message
+            ),
+            None => format!("{}: {}", username, message),
+        }
     }
 
     fn get_created_at(&self) -> DateTime<Utc> {
         self.created_at
     }
+
+    fn get_id(&self) -> Option<MessageId> {
+        self.id
+    }
+}
+
+/// Formats the first line of the original message, e.g. `> alice #12: hi`
+fn format_quote(quote: &Quote) -> String {
+    if quote.body.is_empty() {
+        return format!("> {} #{}: (deleted)", quote.from, quote.id);
+    }
+    let line = quote.body.lines().next().unwrap_or_default();
+    let mut text: String = line.chars().take(QUOTE_MAX_LEN).collect();
+    if text.len() < quote.body.len() {
+        text.push('…');
+    }
+    format!("> {} #{}: {}", quote.from, quote.id, text)
 }
 
 #[derive(Clone, Debug)]
@@ -115,17 +331,31 @@ impl MessageFormatter for Private {
 
 #[derive(Clone, Debug)]
 pub struct Emote {
+    pub id: Option<MessageId>,
     pub created_at: DateTime<Utc>,
     pub from: User,
     pub body: String,
+    // Posted by the server on behalf of the user rather than by them
+    pub system: bool,
 }
 
 impl Emote {
     pub fn new(from: User, body: String) -> Self {
         Self {
+            id: None,
             from,
             body,
             created_at: Utc::now(),
+            system: false,
+        }
+    }
+
+    /// Status change the server posts for a user, e.g. when they go idle.
+    /// It gets no ID, so that it can't be replied to, edited or deleted.
+    pub fn system(from: User, body: String) -> Self {
+        Self {
+            system: true,
+            ..Self::new(from, body)
         }
     }
 }
@@ -139,10 +369,15 @@ impl MessageFormatter for Emote {
     fn get_created_at(&self) -> DateTime<Utc> {
         self.created_at
     }
+
+    fn get_id(&self) -> Option<MessageId> {
+        self.id
+    }
 }
 
 #[derive(Clone, Debug)]
 pub struct Announce {
+    pub id: Option<MessageId>,
     pub created_at: DateTime<Utc>,
     pub from: User,
     pub body: String,
@@ -151,6 +386,7 @@ pub struct Announce {
 impl Announce {
     pub fn new(from: User, body: String) -> Self {
         Self {
+            id: None,
             from,
             body,
             created_at: Utc::now(),
@@ -167,6 +403,10 @@ impl MessageFormatter for Announce {
     fn get_created_at(&self) -> DateTime<Utc> {
         self.created_at
     }
+
+    fn get_id(&self) -> Option<MessageId> {
+        self.id
+    }
 }
 
 #[derive(Clone, Debug)]
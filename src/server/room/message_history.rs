@@ -1,26 +1,94 @@
-use circular_buffer::CircularBuffer;
+use std::collections::vec_deque::Iter;
+use std::collections::{HashSet, VecDeque};
 
-use super::message::Message;
-
-const MESSAGE_HISTORY_LEN: usize = 20;
+use super::message::{Message, MessageFormatter, MessageId};
+use super::user::User;
 
+/// Most recent messages of a channel, replayed to members joining it
 #[derive(Clone)]
 pub struct MessageHistory {
-    buf: CircularBuffer<MESSAGE_HISTORY_LEN, Message>,
+    buf: VecDeque<Message>,
+    capacity: usize,
 }
 
 impl MessageHistory {
-    pub fn new() -> Self {
+    pub fn new(capacity: usize) -> Self {
         Self {
-            buf: CircularBuffer::new(),
+            buf: VecDeque::with_capacity(capacity),
+            capacity,
         }
     }
 
     pub fn push(&mut self, message: Message) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.buf.len() == self.capacity {
+            self.buf.pop_front();
+        }
         self.buf.push_back(message)
     }
 
-    pub fn iter(&self) -> circular_buffer::Iter<Message> {
+    pub fn iter(&self) -> Iter<'_, Message> {
         self.buf.iter()
     }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn find(&self, id: MessageId) -> Option<&Message> {
+        self.buf.iter().find(|message| message.get_id() == Some(id))
+    }
+
+    /// Returns the message with the given ID followed by all the replies to
+    /// it, including replies to replies, in the order they were sent.
+    pub fn thread(&self, id: MessageId) -> Vec<Message> {
+        let mut ids = HashSet::from([id]);
+        let mut messages = vec![];
+        for message in self.buf.iter() {
+            let in_thread = message.get_id() == Some(id)
+                || message
+                    .quote_of_reply()
+                    .is_some_and(|quote| ids.contains(&quote.id));
+            if in_thread {
+                ids.extend(message.get_id());
+                messages.push(message.clone());
+            }
+        }
+        messages
+    }
+
+    /// Returns the most recent message the user sent to the channel
+    pub fn last_sent_by(&self, user: &User) -> Option<&Message> {
+        self.buf
+            .iter()
+            .rev()
+            .find(|message| message.is_sent_by(user))
+    }
+
+    /// Replaces the body of a message, along with the quotes of the replies
+    /// to it. Returns false if the message is not in the history.
+    pub fn edit(&mut self, id: MessageId, body: &str) -> bool {
+        let mut found = false;
+        for message in self.buf.iter_mut() {
+            if message.get_id() == Some(id) {
+                message.set_body(body);
+                found = true;
+            }
+            message.set_quoted_body(id, body);
+        }
+        found
+    }
+
+    /// Removes a message and empties the quotes of the replies to it, so that
+    /// nothing of it is left. Returns false if the message is not in the history.
+    pub fn remove(&mut self, id: MessageId) -> bool {
+        let len = self.buf.len();
+        self.buf.retain(|message| message.get_id() != Some(id));
+        for message in self.buf.iter_mut() {
+            message.set_quoted_body(id, "");
+        }
+        self.buf.len() != len
+    }
 }
@@ -1,10 +1,15 @@
+mod channel;
 mod command;
+mod config;
 mod member;
 mod message_history;
 mod room;
 mod user;
 
 pub mod message;
+pub use channel::{Channel, DEFAULT_CHANNEL_NAME};
+pub use command::*;
+pub use config::*;
+pub use member::RoomMember;
 pub use room::ServerRoom;
 pub use user::*;
-pub use command::*;
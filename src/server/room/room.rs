@@ -1,52 +1,94 @@
-use std::collections::hash_map::IterMut;
+use std::collections::hash_map::{Iter, IterMut, Values};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 
+use anyhow::Context;
 use chrono::{DateTime, Utc};
-use governor::Quota;
-use nonzero_ext::nonzero;
-use russh_keys::key::PublicKey;
+use governor::{Quota, RateLimiter};
+use log::error;
 use tokio::sync::{mpsc, Mutex};
 
+use super::channel::{Channel, DEFAULT_CHANNEL_NAME};
+use super::config::RoomConfig;
 use super::member::RoomMember;
 use super::message;
-use super::message::Message;
-use super::message_history::MessageHistory;
-use super::user::User;
+use super::message::{Message, MessageId};
+use super::user::{RenderMode, User, UserStatus};
 use super::CommandCollection;
 
-use crate::server::ratelimit::RateLimit;
+use crate::server::history::{HistoryRecord, HistoryStore, InputHistoryStore};
+use crate::server::idle::IdleLimits;
+use crate::server::memo::{Memo, MemoStore};
+use crate::server::motd;
+use crate::server::names::NameRegistry;
+use crate::server::ratelimit::{self, KeyedRateLimit};
+use crate::server::reload::ReloadSummary;
+use crate::server::seen::{SeenRecord, SeenStore};
 use crate::server::Auth;
 use crate::utils;
 
 type UserId = usize;
 type UserName = String;
+type ChannelName = String;
 
-const MESSAGE_MAX_BURST: std::num::NonZeroU32 = nonzero!(10u32);
-const MESSAGE_RATE_QUOTA: Quota = Quota::per_second(MESSAGE_MAX_BURST);
+/// Member a command run over exec acts as, see `ServerRoom::attach_exec`
+pub struct ExecAttachment {
+    // Member's user as it was before, to restore how messages are rendered
+    user: User,
+    message_tx: mpsc::Sender<String>,
+}
 
 pub struct ServerRoom {
     names: HashMap<UserId, UserName>,
     members: HashMap<UserName, RoomMember>,
-    ratelims: HashMap<UserId, RateLimit>,
-    history: MessageHistory,
+    channels: HashMap<ChannelName, Channel>,
+    // Kept per member rather than per channel, so that switching channels
+    // does not reset the limit
+    // Message rate limits, separate for every channel of a member
+    ratelims: KeyedRateLimit<(String, UserId)>,
+    // Commands run over exec are limited per key, or per address for
+    // anonymous users, as every exec session is a new member
+    exec_ratelims: KeyedRateLimit,
     commands: CommandCollection,
-    motd: String,
     created_at: DateTime<Utc>,
+    motd_file: Option<String>,
+    config: RoomConfig,
+    history_store: Option<Box<dyn HistoryStore>>,
+    input_history_store: Option<InputHistoryStore>,
+    memo_store: Option<MemoStore>,
+    seen_store: SeenStore,
+    name_registry: NameRegistry,
+    next_message_id: MessageId,
     auth: Arc<Mutex<Auth>>,
 }
 
 impl ServerRoom {
-    pub fn new(motd: &str, auth: Arc<Mutex<Auth>>) -> Self {
+    pub fn new(
+        motd: &str,
+        motd_file: Option<String>,
+        config: RoomConfig,
+        auth: Arc<Mutex<Auth>>,
+    ) -> Self {
+        let default_channel = Channel::new(DEFAULT_CHANNEL_NAME, motd, config.history_len);
+        let ratelims = RateLimiter::keyed(Quota::per_second(config.message_rate));
+        let exec_ratelims = RateLimiter::keyed(Quota::per_second(config.message_rate));
         Self {
             auth,
+            motd_file,
+            config,
+            history_store: None,
+            input_history_store: None,
+            memo_store: None,
+            seen_store: SeenStore::default(),
+            name_registry: NameRegistry::default(),
+            next_message_id: 1,
             names: HashMap::new(),
             members: HashMap::new(),
-            ratelims: HashMap::new(),
-            history: MessageHistory::new(),
+            channels: HashMap::from([(DEFAULT_CHANNEL_NAME.to_string(), default_channel)]),
+            ratelims,
+            exec_ratelims,
             commands: CommandCollection::new(),
-            motd: motd.to_string(),
             created_at: Utc::now(),
         }
     }
@@ -55,14 +97,6 @@ impl ServerRoom {
         &self.commands
     }
 
-    pub fn motd(&self) -> &String {
-        &self.motd
-    }
-
-    pub fn set_motd(&mut self, motd: String) {
-        self.motd = motd;
-    }
-
     pub fn uptime(&self) -> String {
         let now = Utc::now();
         let since_created = now.signed_duration_since(self.created_at).num_seconds() as u64;
@@ -73,8 +107,247 @@ impl ServerRoom {
         &self.auth
     }
 
-    pub fn get_ratelimit(&self, user_id: UserId) -> Option<&RateLimit> {
-        self.ratelims.get(&user_id)
+    pub fn config(&self) -> &RoomConfig {
+        &self.config
+    }
+
+    /// Persists every message kept in the history to the given store. The
+    /// history of the default channel is restored from it right away, other
+    /// channels restore theirs once created.
+    pub fn set_history_store(
+        &mut self,
+        mut store: Box<dyn HistoryStore>,
+    ) -> Result<(), anyhow::Error> {
+        let records = store.load(DEFAULT_CHANNEL_NAME, self.config.history_len)?;
+        let channel = self.find_channel_mut(DEFAULT_CHANNEL_NAME);
+        for record in records {
+            channel.push_history(record.into_message());
+        }
+
+        if let Some(id) = store.last_id()? {
+            self.next_message_id = id + 1;
+        }
+
+        self.history_store = Some(store);
+        Ok(())
+    }
+
+    /// Keeps the command history of users with a public key across sessions
+    pub fn set_input_history_store(&mut self, store: InputHistoryStore) {
+        self.input_history_store = Some(store);
+    }
+
+    /// Returns the inputs the user made in previous sessions, oldest first
+    pub fn load_input_history(&mut self, user: &User) -> Vec<String> {
+        let (Some(store), Some(key)) = (&mut self.input_history_store, &user.public_key) else {
+            return vec![];
+        };
+
+        store.load(&key.fingerprint()).unwrap_or_else(|err| {
+            error!(
+                "Failed to load the input history of {}: {:#}",
+                user.username, err
+            );
+            vec![]
+        })
+    }
+
+    /// Adds an input to the history the user will have in the next sessions
+    pub fn save_input_history(&mut self, user: &User, input: &str) {
+        let (Some(store), Some(key)) = (&mut self.input_history_store, &user.public_key) else {
+            return;
+        };
+
+        if let Err(err) = store.append(&key.fingerprint(), input) {
+            error!(
+                "Failed to save the input history of {}: {:#}",
+                user.username, err
+            );
+        }
+    }
+
+    /// Keeps the messages left with `/tell` until their recipients join
+    pub fn set_memo_store(&mut self, store: MemoStore) {
+        self.memo_store = Some(store);
+    }
+
+    /// Keeps the last-seen records of the users who left across restarts
+    pub fn set_seen_store(&mut self, store: SeenStore) {
+        self.seen_store = store;
+    }
+
+    pub fn seen_store(&self) -> &SeenStore {
+        &self.seen_store
+    }
+
+    /// Keeps the names registered with `/register` across restarts
+    pub fn set_name_registry(&mut self, registry: NameRegistry) {
+        self.name_registry = registry;
+    }
+
+    pub fn name_registry(&self) -> &NameRegistry {
+        &self.name_registry
+    }
+
+    pub fn name_registry_mut(&mut self) -> &mut NameRegistry {
+        &mut self.name_registry
+    }
+
+    /// Queues a message for the user with the given key to get on joining
+    pub fn leave_memo(&mut self, fingerprint: &str, memo: Memo) -> Result<(), anyhow::Error> {
+        match &mut self.memo_store {
+            Some(store) => store.push(fingerprint, memo),
+            None => anyhow::bail!("offline messages are not enabled on this server"),
+        }
+    }
+
+    /// Returns up to `limit` most recent messages of a channel. Only the
+    /// messages kept in memory are available without a history store.
+    pub fn load_history(
+        &mut self,
+        channel_name: &str,
+        limit: usize,
+    ) -> Result<Vec<Message>, anyhow::Error> {
+        if let Some(store) = &mut self.history_store {
+            let records = store.load(channel_name, limit)?;
+            return Ok(records.into_iter().map(|r| r.into_message()).collect());
+        }
+
+        let history = self.find_channel_mut(channel_name).history();
+        let skip = history.len().saturating_sub(limit);
+        Ok(history.iter().skip(skip).cloned().collect())
+    }
+
+    /// Re-reads the oplist, whitelist and motd files and updates the operator
+    /// status of connected members to match the new oplist.
+    pub async fn reload(&mut self) -> Result<ReloadSummary, anyhow::Error> {
+        let motd = match &self.motd_file {
+            Some(path) => Some(motd::load(Some(path)).context("Failed to read the MOTD file")?),
+            None => None,
+        };
+
+        let auth = self.auth.clone();
+        let mut auth = auth.lock().await;
+        let (operators, trusted_keys) = auth.load_keys()?;
+
+        let mut summary = ReloadSummary {
+            operators,
+            trusted_keys,
+            ..Default::default()
+        };
+
+        for member in self.members.values_mut() {
+            let is_op = match &member.user.public_key {
+                Some(key) => auth.is_op(key),
+                None => false,
+            };
+
+            if is_op && !member.user.is_op {
+                summary.granted_op.push(member.user.username.clone());
+            } else if !is_op && member.user.is_op {
+                summary.revoked_op.push(member.user.username.clone());
+            }
+
+            member.user.is_op = is_op;
+        }
+
+        if let Some(motd) = motd {
+            let channel = self.find_channel_mut(DEFAULT_CHANNEL_NAME);
+            summary.motd_changed = channel.motd().ne(&motd);
+            channel.set_motd(motd);
+        }
+
+        Ok(summary)
+    }
+
+    /// Records input from a member and clears the away status the server set
+    /// for them while they were idle.
+    pub async fn register_activity(&mut self, user_id: UserId) {
+        let name = match self.try_get_name(&user_id) {
+            Some(name) => name.clone(),
+            None => return,
+        };
+
+        let member = self.find_member_mut(&name);
+        member.update_last_active_time(Utc::now());
+
+        if member.user.is_auto_away() {
+            member.user.return_active();
+            let message = message::Emote::system(member.user.clone(), "is back".to_string());
+            self.send_message(message.into()).await;
+        }
+    }
+
+    /// Marks members idle for too long as away and disconnects the ones that
+    /// have been idle for longer than the disconnect limit.
+    pub async fn check_idle(&mut self, limits: &IdleLimits) {
+        let mut idle = vec![];
+        let mut expired = vec![];
+
+        for member in self.members.values() {
+            let idle_for = member.idle_duration();
+            if limits
+                .disconnect_after
+                .is_some_and(|limit| idle_for >= limit)
+            {
+                expired.push(member.user.username.clone());
+            } else if limits.away_after.is_some_and(|limit| idle_for >= limit)
+                && matches!(member.user.status, UserStatus::Active)
+            {
+                idle.push(member.user.username.clone());
+            }
+        }
+
+        for name in idle {
+            let member = self.find_member_mut(&name);
+            member.user.go_idle();
+            let message = message::Emote::system(member.user.clone(), "has gone idle".to_string());
+            self.send_message(message.into()).await;
+        }
+
+        for name in expired {
+            let member = self.find_member(&name);
+            let reason = format!(
+                "You were disconnected after being idle for {}",
+                humantime::format_duration(member.idle_duration())
+            );
+            member.disconnect(reason).await;
+        }
+    }
+
+    /// Checks the message rate limit of the member in their current channel
+    pub fn check_ratelimit(&self, username: &UserName) -> Result<(), Duration> {
+        let member = self.find_member(username);
+        let key = (member.channel().clone(), member.user.id);
+        self.ratelims.retain_recent();
+        ratelimit::check_key(&self.ratelims, &key)
+    }
+
+    /// Whether the user is muted, or another session with their key is, so
+    /// that a command run over exec can't speak for a muted member.
+    pub fn is_muted(&self, user: &User) -> bool {
+        let Some(fingerprint) = user.fingerprint() else {
+            return user.is_muted;
+        };
+        user.is_muted
+            || self
+                .members
+                .values()
+                .any(|m| m.user.is_muted && m.user.fingerprint().as_ref() == Some(&fingerprint))
+    }
+
+    /// Checks the rate limit shared by the commands run over exec with the
+    /// user's key, or from their address if they have none.
+    pub fn check_exec_ratelimit(&self, user: &User) -> Result<(), Duration> {
+        let key = match user.fingerprint() {
+            Some(fingerprint) => fingerprint,
+            None => user
+                .peer_addr
+                .map(|addr| addr.ip().to_string())
+                .unwrap_or_default(),
+        };
+        self.exec_ratelims.retain_recent();
+        ratelimit::check_key(&self.exec_ratelims, &key)
     }
 
     pub fn add_member(&mut self, name: UserName, member: RoomMember) {
@@ -89,59 +362,237 @@ impl ServerRoom {
         self.names.insert(id, name);
     }
 
+    pub fn members_iter(&self) -> Iter<'_, UserName, RoomMember> {
+        self.members.iter()
+    }
+
     pub fn members_iter_mut(&mut self) -> IterMut<UserName, RoomMember> {
         self.members.iter_mut()
     }
 
-    pub fn names(&self) -> &HashMap<UserId, UserName> {
-        &self.names
+    pub fn channels(&self) -> Values<'_, ChannelName, Channel> {
+        self.channels.values()
+    }
+
+    pub fn find_channel_mut(&mut self, name: &str) -> &mut Channel {
+        self.channels
+            .get_mut(name)
+            .unwrap_or_else(|| panic!("Channel {name} MUST exist within a server room"))
+    }
+
+    pub fn member_channel(&self, username: &str) -> &Channel {
+        let member = self.find_member(username);
+        self.channels
+            .get(member.channel())
+            .unwrap_or_else(|| panic!("User {username} MUST be a member of an existing channel"))
+    }
+
+    pub fn member_channel_mut(&mut self, username: &str) -> &mut Channel {
+        let channel = self.find_member(username).channel().clone();
+        self.channels
+            .get_mut(&channel)
+            .unwrap_or_else(|| panic!("User {username} MUST be a member of an existing channel"))
     }
 
     pub async fn join(
         &mut self,
-        user_id: UserId,
-        username: UserName,
-        is_op: bool,
-        key: Option<PublicKey>,
-        ssh_id: String,
-        tx: mpsc::Sender<String>,
+        user: User,
+        message_tx: mpsc::Sender<String>,
+        disconnect_tx: mpsc::Sender<String>,
     ) -> User {
-        let name = match self.is_room_member(&username) {
-            true => User::gen_rand_name(),
-            false => username,
-        };
-
-        let user = User::new(user_id, name.clone(), ssh_id, key, is_op);
-        let member = RoomMember::new(user.clone(), tx);
-
-        self.members.insert(name.clone(), member);
-        self.names.insert(user_id, name.clone());
-        self.ratelims
-            .insert(user_id, RateLimit::direct(MESSAGE_RATE_QUOTA));
+        let requested_name = user.username.clone();
+        let user = self.join_quietly(user, message_tx, disconnect_tx);
+        let name = user.username.clone();
 
         self.send_motd(&name).await;
+        if !self
+            .name_registry
+            .is_allowed(&requested_name, user.fingerprint().as_deref())
+        {
+            let member = self.find_member(&name);
+            let message = message::System::new(
+                user.clone(),
+                format!(
+                    "\"{}\" name is registered to another key, you are {} instead",
+                    requested_name, name
+                ),
+            );
+            let _ = member.send_message(message.into()).await;
+        }
+        self.deliver_memos(&name).await;
         self.feed_history(&name).await;
 
         let message = message::Announce::new(
             user.clone(),
-            format!("joined. (Connected: {})", self.members.len()),
+            format!(
+                "joined. (Connected: {})",
+                self.member_channel(&name).members().len()
+            ),
         );
         self.send_message(message.into()).await;
 
         user
     }
 
+    /// Adds a member to the default channel without the welcome message,
+    /// history or announcement, e.g. for a single command run over SSH exec.
+    pub fn join_quietly(
+        &mut self,
+        mut user: User,
+        message_tx: mpsc::Sender<String>,
+        disconnect_tx: mpsc::Sender<String>,
+    ) -> User {
+        let fingerprint = user.fingerprint();
+        if self.is_room_member(&user.username)
+            || !self
+                .name_registry
+                .is_allowed(&user.username, fingerprint.as_deref())
+        {
+            user.set_new_name(User::gen_rand_name());
+        }
+
+        user.set_theme(self.config.theme.clone());
+        user.set_timestamp_mode(self.config.timestamp_mode.clone());
+
+        let user_id = user.id;
+        let name = user.username.clone();
+        let channel = DEFAULT_CHANNEL_NAME.to_string();
+        let member = RoomMember::new(user.clone(), channel.clone(), message_tx, disconnect_tx);
+
+        self.members.insert(name.clone(), member);
+        self.names.insert(user_id, name);
+        self.enter_channel(user_id, &channel);
+
+        user
+    }
+
+    /// Lets a command run over exec act as the member connected with the same
+    /// name and key, so that it keeps their name, channel and mute status.
+    /// The member's messages go to the given sender as plain text until
+    /// `detach_exec` is called. Returns `None` if there is no such member.
+    pub fn attach_exec(
+        &mut self,
+        user: &User,
+        message_tx: mpsc::Sender<String>,
+    ) -> Option<ExecAttachment> {
+        let member = self.members.get_mut(&user.username)?;
+        if user.public_key.is_none() || member.user.public_key != user.public_key {
+            return None;
+        }
+
+        let attachment = ExecAttachment {
+            user: member.user.clone(),
+            message_tx: member.replace_message_sender(message_tx),
+        };
+        member.user.set_render_mode(RenderMode::Plain);
+        Some(attachment)
+    }
+
+    /// Gives the member their messages back once the exec command is done.
+    pub fn detach_exec(&mut self, attachment: ExecAttachment) {
+        if let Some(member) = self.members.get_mut(&attachment.user.username) {
+            member.replace_message_sender(attachment.message_tx);
+            member.user.render_mode = attachment.user.render_mode;
+            member.user.theme = attachment.user.theme;
+        }
+    }
+
+    /// Moves a member from their current channel to the given one. The channel
+    /// is created if it does not exist yet.
+    pub async fn join_channel(&mut self, username: &UserName, channel_name: &str) {
+        let member = self.find_member(username);
+        let user = member.user.clone();
+        let prev_channel = member.channel().clone();
+
+        let message = message::Announce::new(user.clone(), format!("left for {}", channel_name));
+        self.send_message(message.into()).await;
+
+        self.exit_channel(user.id, &prev_channel);
+        self.enter_channel(user.id, channel_name);
+        self.find_member_mut(username)
+            .set_channel(channel_name.to_string());
+
+        self.send_motd(username).await;
+        self.feed_history(username).await;
+
+        let message = message::Announce::new(
+            user,
+            format!(
+                "joined {}. (Connected: {})",
+                channel_name,
+                self.member_channel(username).members().len()
+            ),
+        );
+        self.send_message(message.into()).await;
+    }
+
     pub async fn send_motd(&mut self, username: &UserName) {
-        let motd = self.motd.clone();
+        let channel = self.member_channel(username);
+        let motd = channel.motd().clone();
+        let topic = channel.topic().clone();
+
         let member = self.find_member(username);
-        let message =
-            message::System::new(member.user.clone(), format!("{}{}", motd, utils::NEWLINE));
+        if !motd.is_empty() {
+            let message =
+                message::System::new(member.user.clone(), format!("{}{}", motd, utils::NEWLINE));
+            let _ = member.send_message(message.into()).await;
+        }
+        if !topic.is_empty() {
+            let message = message::System::new(member.user.clone(), format!("Topic: {}", topic));
+            let _ = member.send_message(message.into()).await;
+        }
+    }
+
+    /// Sends the messages left for the member while they were offline as
+    /// private messages from their authors. Authors whose name is not
+    /// registered to the key they left the message with are shown with the
+    /// start of its fingerprint, as anyone could have used their name.
+    pub async fn deliver_memos(&mut self, username: &UserName) {
+        let Some(fingerprint) = self.find_member(username).user.fingerprint() else {
+            return;
+        };
+        let Some(store) = &mut self.memo_store else {
+            return;
+        };
+
+        let memos = store.take(&fingerprint).unwrap_or_else(|err| {
+            error!("Failed to load the memos of {}: {:#}", username, err);
+            vec![]
+        });
+        if memos.is_empty() {
+            return;
+        }
+
+        let member = self.find_member(username);
+        let message = message::System::new(
+            member.user.clone(),
+            "Messages left for you while you were offline:".to_string(),
+        );
         let _ = member.send_message(message.into()).await;
+
+        for memo in memos {
+            let registered = self
+                .name_registry
+                .find(&memo.from)
+                .is_some_and(|r| r.fingerprint == memo.from_fingerprint);
+            let from_name = if registered {
+                memo.from
+            } else {
+                let short = memo.from_fingerprint.chars().take(8).collect::<String>();
+                format!("{} (SHA256:{})", memo.from, short)
+            };
+
+            let from = User::new(0, from_name, String::new(), None, None, false);
+            let mut message = message::Private::new(from, member.user.clone(), memo.body);
+            message.created_at = memo.created_at;
+            let _ = member.send_message(message.into()).await;
+        }
     }
 
     pub async fn feed_history(&mut self, username: &UserName) {
         let member = self.find_member(username);
-        for msg in self.history.iter() {
+        let channel = self.member_channel(username);
+        for msg in channel.history().iter() {
             if let Err(_) = member.send_message(msg.to_owned()).await {
                 continue;
             }
@@ -156,14 +607,61 @@ impl ServerRoom {
 
         let username = name.unwrap().clone();
         let user = self.find_member(&username).user.clone();
-
         let duration = humantime::format_duration(user.joined_duration());
         let message = message::Announce::new(user, format!("left: (After {})", duration));
         self.send_message(message.into()).await;
 
+        self.save_seen(&username);
+        self.leave_quietly(user_id);
+    }
+
+    fn save_seen(&mut self, username: &UserName) {
+        let member = self.find_member(username);
+        let record = SeenRecord {
+            username: username.clone(),
+            fingerprint: member.user.fingerprint(),
+            joined_at: member.user.joined_at,
+            left_at: Utc::now(),
+            last_sent_at: *member.last_sent_time(),
+            away: match &member.user.status {
+                UserStatus::Away { reason, .. } => Some(reason.clone()),
+                UserStatus::Active => None,
+            },
+        };
+        if let Err(err) = self.seen_store.save(record) {
+            error!("Failed to save when {} was last seen: {:#}", username, err);
+        }
+    }
+
+    /// Removes a member without announcing it to the channel or recording
+    /// when they were last seen, e.g. after a single command run over SSH
+    /// exec, which says nothing about the user being around.
+    pub fn leave_quietly(&mut self, user_id: &UserId) {
+        let username = match self.try_get_name(user_id) {
+            Some(name) => name.clone(),
+            None => return,
+        };
+        let member = self.find_member(&username);
+        let channel = member.channel().clone();
+        let fingerprint = member.user.fingerprint();
+
         self.members.remove(&username);
         self.names.remove(user_id);
-        self.ratelims.remove(user_id);
+        self.exit_channel(*user_id, &channel);
+
+        if let (Some(store), Some(fingerprint)) = (&mut self.input_history_store, fingerprint) {
+            // Other sessions of the same key keep sharing the history
+            let keep = self
+                .members
+                .values()
+                .any(|m| m.user.fingerprint().as_ref() == Some(&fingerprint));
+            if let Err(err) = store.save(&fingerprint, keep) {
+                error!(
+                    "Failed to save the input history of {}: {:#}",
+                    username, err
+                );
+            }
+        }
 
         for (_, member) in &mut self.members {
             member.user.ignored.remove(user_id);
@@ -171,25 +669,38 @@ impl ServerRoom {
         }
     }
 
-    pub async fn send_message(&mut self, msg: Message) {
+    pub async fn send_message(&mut self, mut msg: Message) {
+        // Messages kept in the history get an ID to refer to them by, except
+        // the status changes the server posts for users
+        if let Message::Public(_)
+        | Message::Emote(message::Emote { system: false, .. })
+        | Message::Announce(_) = msg
+        {
+            msg.set_id(self.next_message_id);
+            self.next_message_id += 1;
+        }
+
         match msg {
             Message::System(ref m) => {
                 let member = self.find_member(&m.from.username);
-                member.send_message(msg).await.unwrap();
+                let _ = member.send_message(msg).await;
             }
             Message::Command(ref m) => {
                 let member = self.find_member(&m.from.username);
-                member.send_message(msg).await.unwrap();
+                let _ = member.send_message(msg).await;
             }
             Message::Error(ref m) => {
                 let member = self.find_member(&m.from.username);
-                member.send_message(msg).await.unwrap();
+                let _ = member.send_message(msg).await;
             }
             Message::Public(ref m) => {
-                self.history.push(msg.clone());
-                for (_, member) in self.members.iter() {
+                let channel = match self.push_to_channel_history(&m.from.username, &msg) {
+                    Some(channel) => channel,
+                    None => return,
+                };
+                for member in self.channel_members_iter(&channel) {
                     if m.from.is_muted && member.user.id == m.from.id {
-                        member.send_user_is_muted_message().await.unwrap();
+                        let _ = member.send_user_is_muted_message().await;
                     }
                     if m.from.is_muted {
                         continue;
@@ -207,10 +718,13 @@ impl ServerRoom {
                 }
             }
             Message::Emote(ref m) => {
-                self.history.push(msg.clone());
-                for (_, member) in self.members.iter() {
+                let channel = match self.push_to_channel_history(&m.from.username, &msg) {
+                    Some(channel) => channel,
+                    None => return,
+                };
+                for member in self.channel_members_iter(&channel) {
                     if m.from.is_muted && member.user.id == m.from.id {
-                        member.send_user_is_muted_message().await.unwrap();
+                        let _ = member.send_user_is_muted_message().await;
                     }
                     if m.from.is_muted {
                         continue;
@@ -224,10 +738,13 @@ impl ServerRoom {
                 }
             }
             Message::Announce(ref m) => {
-                self.history.push(msg.clone());
-                for (_, member) in self.members.iter() {
+                let channel = match self.push_to_channel_history(&m.from.username, &msg) {
+                    Some(channel) => channel,
+                    None => return,
+                };
+                for member in self.channel_members_iter(&channel) {
                     if m.from.is_muted && member.user.id == m.from.id {
-                        member.send_user_is_muted_message().await.unwrap();
+                        let _ = member.send_user_is_muted_message().await;
                     }
                     if m.from.is_muted {
                         continue;
@@ -247,40 +764,154 @@ impl ServerRoom {
                 let from = self.find_member(&m.from.username);
 
                 if m.from.is_muted {
-                    from.send_user_is_muted_message().await.unwrap();
+                    let _ = from.send_user_is_muted_message().await;
                     return;
                 }
 
-                from.send_message(msg.clone()).await.unwrap();
+                let _ = from.send_message(msg.clone()).await;
 
                 let to = self.find_member(&m.to.username);
                 if !to.user.ignored.contains(&m.from.id) {
-                    to.send_message(msg).await.unwrap();
+                    let _ = to.send_message(msg).await;
                 }
             }
         }
     }
 
-    pub fn find_name_by_prefix(&self, prefix: &str, skip: &str) -> Option<String> {
+    /// Replaces the body of a message in the history of the user's channel
+    /// and lets the members of the channel know about the correction.
+    pub async fn edit_message(&mut self, user: &User, id: MessageId, body: String) {
+        let channel = self.member_channel_mut(&user.username);
+        if !channel.history_mut().edit(id, &body) {
+            return;
+        }
+        let channel = channel.name().clone();
+
+        if let Some(store) = &mut self.history_store {
+            if let Err(err) = store.edit(id, &body) {
+                error!("Failed to edit message #{} in the history: {:#}", id, err);
+            }
+        }
+
+        let message = message::Announce::new(user.clone(), format!("edited #{}: {}", id, body));
+        self.send_correction(&channel, message).await;
+    }
+
+    /// Removes a message from the history of the user's channel, so that it
+    /// is not replayed to anyone joining it, and lets its members know.
+    pub async fn delete_message(&mut self, user: &User, id: MessageId) {
+        let channel = self.member_channel_mut(&user.username);
+        if !channel.history_mut().remove(id) {
+            return;
+        }
+        let channel = channel.name().clone();
+
+        if let Some(store) = &mut self.history_store {
+            if let Err(err) = store.delete(id) {
+                error!(
+                    "Failed to delete message #{} from the history: {:#}",
+                    id, err
+                );
+            }
+        }
+
+        let message = message::Announce::new(user.clone(), format!("deleted #{}", id));
+        self.send_correction(&channel, message).await;
+    }
+
+    /// Sends a correction to everyone in the channel who could have seen the
+    /// original message. Unlike announcements, it is not kept in the history.
+    async fn send_correction(&mut self, channel: &str, correction: message::Announce) {
+        for member in self.channel_members_iter(channel) {
+            if member.user.ignored.contains(&correction.from.id) {
+                continue;
+            }
+            let _ = member.send_message(correction.clone().into()).await;
+        }
+    }
+
+    fn enter_channel(&mut self, user_id: UserId, channel_name: &str) {
+        if !self.channels.contains_key(channel_name) {
+            let channel = self.create_channel(channel_name);
+            self.channels.insert(channel_name.to_string(), channel);
+        }
+        self.find_channel_mut(channel_name).add_member(user_id);
+    }
+
+    fn create_channel(&mut self, channel_name: &str) -> Channel {
+        let mut channel = Channel::new(channel_name, "", self.config.history_len);
+
+        let records = match &mut self.history_store {
+            Some(store) => store.load(channel_name, self.config.history_len),
+            None => Ok(vec![]),
+        };
+
+        match records {
+            Ok(records) => {
+                for record in records {
+                    channel.push_history(record.into_message());
+                }
+            }
+            Err(err) => error!("Failed to load the history of {}: {:#}", channel_name, err),
+        }
+
+        channel
+    }
+
+    fn exit_channel(&mut self, user_id: UserId, channel_name: &str) {
+        if let Some(channel) = self.channels.get_mut(channel_name) {
+            channel.remove_member(&user_id);
+            if channel.is_empty() && !channel.is_default() {
+                self.channels.remove(channel_name);
+            }
+        }
+    }
+
+    fn push_to_channel_history(&mut self, username: &str, msg: &Message) -> Option<ChannelName> {
+        let member = self.try_find_member(username)?;
+        let channel = member.channel().clone();
+
+        // Messages of muted users are never delivered, so they are not kept either
+        if member.user.is_muted {
+            return Some(channel);
+        }
+
+        self.channels.get_mut(&channel)?.push_history(msg.clone());
+
+        if let Some(store) = &mut self.history_store {
+            if let Some(record) = HistoryRecord::from_message(&channel, msg) {
+                if let Err(err) = store.append(&record) {
+                    error!("Failed to save a message to the history: {:#}", err);
+                }
+            }
+        }
+
+        Some(channel)
+    }
+
+    fn channel_members_iter<'a>(
+        &'a self,
+        channel: &'a str,
+    ) -> impl Iterator<Item = &'a RoomMember> + 'a {
+        self.members
+            .values()
+            .filter(move |member| member.channel() == channel)
+    }
+
+    /// Returns the names of other members starting with the prefix, the
+    /// recently active ones first
+    pub fn find_names_by_prefix(&self, prefix: &str, skip: &str) -> Vec<String> {
         let mut members = vec![];
         for member in self.members.values() {
-            if member.user.username.starts_with(prefix) {
+            let username = &member.user.username;
+            if username.starts_with(prefix) && username != skip {
                 members.push(member.clone());
             }
         }
 
         // Sort in descending order (recently active first)
         members.sort_by(|a, b| b.last_sent_time().cmp(&a.last_sent_time()));
-
-        let names: Vec<&String> = members.iter().map(|m| &m.user.username).collect();
-        if names.is_empty() {
-            return None;
-        } else if names[0] != skip {
-            return Some(names[0].clone());
-        } else if names.len() > 1 {
-            return Some(names[1].clone());
-        }
-        None
+        members.into_iter().map(|m| m.user.username).collect()
     }
 
     pub fn find_member(&self, username: &str) -> &RoomMember {
@@ -299,10 +930,9 @@ impl ServerRoom {
         self.members.contains_key(username)
     }
 
-    pub fn find_member_by_id(&mut self, user_id: UserId) -> &RoomMember {
+    pub fn try_find_member_by_id(&self, user_id: UserId) -> Option<&RoomMember> {
         self.try_get_name(&user_id)
             .and_then(|name| self.try_find_member(name))
-            .expect(format!("User {user_id} MUST have an member within a server room").as_str())
     }
 
     pub fn try_find_member(&self, username: &str) -> Option<&RoomMember> {
@@ -1,8 +1,10 @@
+mod render_mode;
 mod status;
 mod theme;
 mod timestamp_mode;
 mod user;
 
+pub use render_mode::RenderMode;
 pub use status::UserStatus;
 pub use theme::Theme;
 pub use timestamp_mode::TimestampMode;
@@ -0,0 +1,13 @@
+/// How the messages and the input line are rendered for a session
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RenderMode {
+    /// Interactive terminal with a prompt, colors and line editing
+    #[default]
+    Terminal,
+    /// Terminal without colors or cursor movements, where messages are
+    /// appended as plain lines, e.g. for dumb terminals and screen readers
+    Plain,
+    /// One JSON object per message and one input line per message or
+    /// command, for bots and scripts connecting without a pty
+    Json,
+}
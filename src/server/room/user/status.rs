@@ -6,6 +6,8 @@ pub enum UserStatus {
     Away {
         reason: String,
         since: DateTime<Utc>,
+        /// Set by the server after the user was idle for too long
+        auto: bool,
     },
 }
 
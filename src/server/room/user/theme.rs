@@ -87,6 +87,7 @@ impl Into<UserTheme> for Theme {
                 tagged_username_fg: ThemeColor::Black.into(),
                 tagged_username_bg: ThemeColor::DarkYellow.into(),
                 username_fg: |s| ThemeColor::FromString(s).into(),
+                plain: false,
             },
             Theme::Mono => UserTheme {
                 text_fg: ThemeColor::White.into(),
@@ -94,6 +95,7 @@ impl Into<UserTheme> for Theme {
                 tagged_username_fg: ThemeColor::White.into(),
                 tagged_username_bg: ThemeColor::DarkGrey.into(),
                 username_fg: |_| ThemeColor::White.into(),
+                plain: false,
             },
             Theme::Hacker => UserTheme {
                 text_fg: ThemeColor::Green.into(),
@@ -101,6 +103,7 @@ impl Into<UserTheme> for Theme {
                 tagged_username_fg: ThemeColor::DarkGreen.into(),
                 tagged_username_bg: ThemeColor::Green.into(),
                 username_fg: |_| ThemeColor::Green.into(),
+                plain: false,
             },
         }
     }
@@ -113,6 +116,7 @@ pub struct UserTheme {
     username_fg: fn(String) -> Color,
     tagged_username_fg: Color,
     tagged_username_bg: Color,
+    plain: bool,
 }
 
 impl Default for UserTheme {
@@ -122,19 +126,39 @@ impl Default for UserTheme {
 }
 
 impl UserTheme {
+    /// Theme without any styling, so that no escape sequences are written
+    pub fn plain() -> Self {
+        Self {
+            plain: true,
+            ..Theme::Mono.into()
+        }
+    }
+
     pub fn style_text<'a>(&self, s: &'a str) -> StyledContent<&'a str> {
+        if self.plain {
+            return s.stylize();
+        }
         s.with(self.text_fg)
     }
 
     pub fn style_system_text<'a>(&self, s: &'a str) -> StyledContent<&'a str> {
+        if self.plain {
+            return s.stylize();
+        }
         s.with(self.system_text_fg)
     }
 
     pub fn style_username<'a>(&self, s: &'a str) -> StyledContent<&'a str> {
+        if self.plain {
+            return s.stylize();
+        }
         s.with(self.get_username_fg(s))
     }
 
     pub fn style_tagged_username<'a>(&self, s: &'a str) -> StyledContent<&'a str> {
+        if self.plain {
+            return s.stylize();
+        }
         s.on(self.tagged_username_bg)
             .with(self.tagged_username_fg)
             .attribute(Attribute::Bold)
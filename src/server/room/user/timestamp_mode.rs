@@ -15,6 +15,10 @@ impl Default for TimestampMode {
 }
 
 impl TimestampMode {
+    pub fn all() -> Vec<String> {
+        ["time", "datetime", "off"].map(String::from).to_vec()
+    }
+
     pub fn format(&self) -> Option<&str> {
         match self {
             TimestampMode::Time => Some("%H:%M"),
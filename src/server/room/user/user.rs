@@ -2,12 +2,13 @@ use chrono::{DateTime, Utc};
 use rand::seq::SliceRandom;
 use rand::Rng;
 use russh_keys::key::PublicKey;
-use std::{collections::BTreeSet, fmt::Display, time::Duration};
+use std::{collections::BTreeSet, fmt::Display, net::SocketAddr, time::Duration};
 
 use crate::utils;
 
+use super::render_mode::RenderMode;
 use super::status::UserStatus;
-use super::theme::UserTheme;
+use super::theme::{Theme, UserTheme};
 use super::timestamp_mode::TimestampMode;
 
 #[derive(Clone, Debug)]
@@ -17,13 +18,16 @@ pub struct User {
     pub status: UserStatus,
     pub joined_at: DateTime<Utc>,
     pub ssh_client: String,
+    pub peer_addr: Option<SocketAddr>,
     pub public_key: Option<PublicKey>,
     pub reply_to: Option<usize>,
     pub theme: UserTheme,
     pub quiet: bool,
+    pub show_ids: bool,
     pub is_op: bool,
     pub is_muted: bool,
     pub timestamp_mode: TimestampMode,
+    pub render_mode: RenderMode,
     pub ignored: BTreeSet<usize>,
     pub focused: BTreeSet<usize>,
 }
@@ -33,6 +37,7 @@ impl User {
         id: usize,
         username: String,
         ssh_client: String,
+        peer_addr: Option<SocketAddr>,
         key: Option<PublicKey>,
         is_op: bool,
     ) -> Self {
@@ -40,15 +45,18 @@ impl User {
             id,
             username,
             ssh_client,
+            peer_addr,
             is_op,
             public_key: key,
             joined_at: Utc::now(),
             reply_to: None,
             quiet: false,
+            show_ids: false,
             is_muted: false,
             status: Default::default(),
             theme: Default::default(),
             timestamp_mode: Default::default(),
+            render_mode: Default::default(),
             ignored: BTreeSet::new(),
             focused: BTreeSet::new(),
         }
@@ -58,10 +66,29 @@ impl User {
         self.quiet = !self.quiet;
     }
 
+    pub fn switch_ids_mode(&mut self) {
+        self.show_ids = !self.show_ids;
+    }
+
     pub fn switch_mute_mode(&mut self) {
         self.is_muted = !self.is_muted;
     }
 
+    /// Themes have no effect in the plain render mode, which has no colors
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = match self.render_mode {
+            RenderMode::Plain => UserTheme::plain(),
+            RenderMode::Terminal | RenderMode::Json => theme.into(),
+        };
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+        if mode == RenderMode::Plain {
+            self.theme = UserTheme::plain();
+        }
+    }
+
     pub fn set_timestamp_mode(&mut self, mode: TimestampMode) {
         self.timestamp_mode = mode;
     }
@@ -70,9 +97,24 @@ impl User {
         self.status = UserStatus::Away {
             reason,
             since: Utc::now(),
+            auto: false,
         };
     }
 
+    /// Marks the user as away on their behalf. Unlike `/away`, this status is
+    /// cleared as soon as the user types anything.
+    pub fn go_idle(&mut self) {
+        self.status = UserStatus::Away {
+            reason: "idle".to_string(),
+            since: Utc::now(),
+            auto: true,
+        };
+    }
+
+    pub fn is_auto_away(&self) -> bool {
+        matches!(self.status, UserStatus::Away { auto: true, .. })
+    }
+
     pub fn return_active(&mut self) {
         self.status = UserStatus::Active;
     }
@@ -81,6 +123,11 @@ impl User {
         self.username = username;
     }
 
+    /// Fingerprint of the key the user connected with, if any
+    pub fn fingerprint(&self) -> Option<String> {
+        self.public_key.as_ref().map(|key| key.fingerprint())
+    }
+
     pub fn joined_duration(&self) -> Duration {
         let now = Utc::now();
         let secs = now.signed_duration_since(self.joined_at).num_seconds() as u64;
@@ -133,7 +180,7 @@ impl Display for User {
 
         match &self.status {
             UserStatus::Active => Ok(()),
-            UserStatus::Away { reason, since } => {
+            UserStatus::Away { reason, since, .. } => {
                 let now = Utc::now();
                 let secs = now.signed_duration_since(since).num_seconds() as u64;
                 write!(
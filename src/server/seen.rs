@@ -0,0 +1,107 @@
+use std::io;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::utils;
+
+/// Number of users remembered, the ones seen longest ago are forgotten first
+const SEEN_MAX_RECORDS: usize = 1000;
+
+/// Last session of a user, kept after they leave
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeenRecord {
+    pub username: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    pub joined_at: DateTime<Utc>,
+    pub left_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_sent_at: Option<DateTime<Utc>>,
+    // Reason of the away status the user left with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub away: Option<String>,
+}
+
+/// Last-seen records of the users who left, one per name and key
+/// fingerprint. They are kept in memory only unless a file is given.
+#[derive(Default)]
+pub struct SeenStore {
+    path: Option<PathBuf>,
+    records: Vec<SeenRecord>,
+}
+
+impl SeenStore {
+    /// Loads the records from a file of JSON encoded records, one per line.
+    /// Every later change is written back to the same file.
+    pub fn open(path: PathBuf) -> Result<Self, anyhow::Error> {
+        let lines = match utils::fs::read_file_lines(&path.to_string_lossy()) {
+            Ok(lines) => lines,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => vec![],
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("Failed to read the last-seen file {}", path.display())
+                })
+            }
+        };
+
+        let mut records = vec![];
+        for (idx, line) in lines.iter().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            match serde_json::from_slice::<SeenRecord>(line) {
+                Ok(record) => records.push(record),
+                Err(err) => warn!(
+                    "Skipped line {} of the last-seen file {}: {}",
+                    idx + 1,
+                    path.display(),
+                    err
+                ),
+            }
+        }
+        records.sort_by_key(|record| record.left_at);
+
+        Ok(Self {
+            path: Some(path),
+            records,
+        })
+    }
+
+    /// Replaces the record of the same name and key, if any
+    pub fn save(&mut self, record: SeenRecord) -> Result<(), anyhow::Error> {
+        self.records
+            .retain(|r| r.username != record.username || r.fingerprint != record.fingerprint);
+        self.records.push(record);
+        let skip = self.records.len().saturating_sub(SEEN_MAX_RECORDS);
+        self.records.drain(..skip);
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let mut contents = String::new();
+        for record in self.records.iter() {
+            contents.push_str(&serde_json::to_string(record)?);
+            contents.push('\n');
+        }
+        utils::fs::write_file_atomically(&path.to_string_lossy(), &contents)
+            .with_context(|| format!("Failed to write the last-seen file {}", path.display()))
+    }
+
+    /// Returns the most recent record of a user name
+    pub fn find_by_name(&self, username: &str) -> Option<&SeenRecord> {
+        self.records.iter().rev().find(|r| r.username == username)
+    }
+
+    /// Returns the most recent record of a key fingerprint
+    pub fn find_by_fingerprint(&self, fingerprint: &str) -> Option<&SeenRecord> {
+        self.records
+            .iter()
+            .rev()
+            .find(|r| r.fingerprint.as_deref() == Some(fingerprint))
+    }
+}
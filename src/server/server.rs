@@ -1,20 +1,33 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
-use log::info;
+use anyhow::Context;
+use futures::future::try_join_all;
+use log::{error, info};
 use russh::server::{Config, Server};
 use russh_keys::key::KeyPair;
+use tokio::net::TcpListener;
 use tokio::spawn;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::Mutex;
 
 use super::session::{SessionRepositoryEvent, ThinHandler};
-use super::{Auth, ServerRoom, SessionRepository};
+use super::{idle, Auth, IdleLimits, ServerRoom, SessionRepository};
+
+/// Network and session level settings of the server
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub listen: Vec<SocketAddr>,
+    pub inactivity_timeout: Option<Duration>,
+    pub idle_limits: IdleLimits,
+}
 
 #[derive(Clone)]
 pub struct AppServer {
-    id_increment: usize,
-    port: u16,
+    id_increment: Arc<AtomicUsize>,
+    config: ServerConfig,
     server_keys: Vec<KeyPair>,
     auth: Arc<Mutex<Auth>>,
     room: Arc<Mutex<ServerRoom>>,
@@ -23,16 +36,16 @@ pub struct AppServer {
 
 impl AppServer {
     pub fn new(
-        port: u16,
+        config: ServerConfig,
         auth: Arc<Mutex<Auth>>,
         room: ServerRoom,
         server_keys: &[KeyPair],
         repo_event_sender: Sender<SessionRepositoryEvent>,
     ) -> Self {
         Self {
-            port,
+            config,
             auth,
-            id_increment: 0,
+            id_increment: Arc::new(AtomicUsize::new(0)),
             room: Arc::new(Mutex::new(room)),
             server_keys: server_keys.to_vec(),
             repo_event_sender,
@@ -47,20 +60,69 @@ impl AppServer {
             repository.wait_for_sessions(room).await;
         });
 
+        #[cfg(unix)]
+        {
+            let room = self.room.clone();
+            info!("Spawning a thread to reload server files on SIGHUP");
+            spawn(Self::reload_on_sighup(room));
+        }
+
+        if !self.config.idle_limits.is_empty() {
+            let room = self.room.clone();
+            info!("Spawning a thread to watch for idle members");
+            spawn(idle::watch(room, self.config.idle_limits));
+        }
+
         let config = Config {
-            inactivity_timeout: Some(Duration::from_secs(3600)),
+            inactivity_timeout: self.config.inactivity_timeout,
             auth_rejection_time: Duration::from_secs(3),
             auth_rejection_time_initial: Some(Duration::from_secs(0)),
             keys: self.server_keys.clone(),
             ..Default::default()
         };
 
-        info!("Server is running on {} port!", self.port);
-        self.run_on_address(Arc::new(config), ("0.0.0.0", self.port))
-            .await?;
+        // Bind every address up front so that a bad one fails the startup
+        let mut listeners = Vec::with_capacity(self.config.listen.len());
+        for addr in &self.config.listen {
+            let listener = TcpListener::bind(addr)
+                .await
+                .with_context(|| format!("Failed to listen on {}", addr))?;
+            info!("Server is listening on {}", addr);
+            listeners.push(listener);
+        }
+
+        // All listeners share the session id counter and the repository
+        let config = Arc::new(config);
+        try_join_all(listeners.into_iter().map(|listener| {
+            let mut server = self.clone();
+            let config = config.clone();
+            async move { server.run_on_socket(config, &listener).await }
+        }))
+        .await?;
 
         Ok(())
     }
+
+    #[cfg(unix)]
+    async fn reload_on_sighup(room: Arc<Mutex<ServerRoom>>) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(err) => {
+                error!("Failed to listen for SIGHUP: {}", err);
+                return;
+            }
+        };
+
+        while hangup.recv().await.is_some() {
+            info!("Received SIGHUP, reloading oplist, whitelist and motd");
+            match room.lock().await.reload().await {
+                Ok(summary) => info!("Reloaded: {}", summary),
+                Err(err) => error!("Failed to reload: {:#}", err),
+            }
+        }
+    }
 }
 
 /// Trait used to create new handlers when clients connect
@@ -69,9 +131,10 @@ impl Server for AppServer {
 
     fn new_client(&mut self, peer_addr: Option<std::net::SocketAddr>) -> Self::Handler {
         info!("New client created for peer {:?}", peer_addr);
-        self.id_increment += 1;
+        let id = self.id_increment.fetch_add(1, Ordering::Relaxed) + 1;
         Self::Handler::new(
-            self.id_increment,
+            id,
+            peer_addr,
             self.auth.clone(),
             self.repo_event_sender.clone(),
         )
@@ -1,3 +1,4 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use anyhow::Ok;
@@ -13,10 +14,11 @@ use russh::MethodSet;
 use russh::Pty;
 use russh_keys::key::PublicKey;
 use tokio::spawn;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::Mutex;
 
 use crate::server::auth;
+use crate::server::room::RenderMode;
 use crate::server::terminal::TerminalHandle;
 
 use super::SessionEvent;
@@ -25,71 +27,96 @@ use super::SessionRepositoryEvent;
 /// Server handler. Each client will have their own handler.
 pub struct ThinHandler {
     id: usize,
+    peer_addr: Option<SocketAddr>,
     connect_username: String,
     public_key: Option<PublicKey>,
     auth: Arc<Mutex<auth::Auth>>,
     repo_event_sender: Sender<SessionRepositoryEvent>,
     session_event_sender: Option<Sender<SessionEvent>>,
+    session_event_receiver: Option<Receiver<SessionEvent>>,
+    pty_requested: bool,
+    json_requested: bool,
+    plain_requested: bool,
+    ban: Option<auth::BanEntry>,
+    ban_shown: bool,
 }
 
 impl ThinHandler {
     pub fn new(
         id: usize,
+        peer_addr: Option<SocketAddr>,
         auth: Arc<Mutex<auth::Auth>>,
         repo_event_sender: Sender<SessionRepositoryEvent>,
     ) -> ThinHandler {
         ThinHandler {
             id,
+            peer_addr,
             connect_username: String::new(),
             public_key: None,
             auth,
             repo_event_sender,
             session_event_sender: None,
+            session_event_receiver: None,
+            pty_requested: false,
+            json_requested: false,
+            plain_requested: false,
+            ban: None,
+            ban_shown: false,
         }
     }
+
+    async fn find_ban(&self, user: &str, key: Option<&PublicKey>) -> Option<auth::BanEntry> {
+        let ip = self.peer_addr.map(|addr| addr.ip());
+        let auth = self.auth.lock().await;
+        auth.find_ban(user, key, ip.as_ref()).cloned()
+    }
+
+    async fn is_op(&self) -> bool {
+        match &self.public_key {
+            Some(key) => self.auth.lock().await.is_op(key),
+            None => false,
+        }
+    }
+
+    /// Banned clients are rejected before they are authenticated. They may
+    /// only go on with keyboard-interactive, which is used to show them the
+    /// reason of the ban.
+    fn reject_banned(&mut self, ban: auth::BanEntry) -> Auth {
+        info!(
+            "Rejected banned client for session id={} from peer {:?}: {}",
+            self.id, self.peer_addr, ban.attribute
+        );
+        self.ban = Some(ban);
+        Auth::Reject {
+            proceed_with_methods: Some(MethodSet::KEYBOARD_INTERACTIVE),
+        }
+    }
+
+    /// Closes the connection of a banned client who keeps trying to
+    /// authenticate after being shown the reason of the ban.
+    fn close_if_ban_shown(&self) -> Result<(), anyhow::Error> {
+        if self.ban_shown {
+            anyhow::bail!("Closed the connection of banned session id={}", self.id);
+        }
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
 impl Handler for ThinHandler {
     type Error = anyhow::Error;
 
+    #[allow(unused_variables)]
     async fn channel_open_session(
         &mut self,
         channel: Channel<Msg>,
         session: &mut Session,
     ) -> Result<bool, Self::Error> {
-        info!("Starting a new session id={}", self.id);
-
-        let id = self.id;
-        let connect_username = self.connect_username.clone();
-        let ssh_id = String::from_utf8_lossy(session.remote_sshid()).to_string();
-        let key = self.public_key.clone();
-        let terminal_handle = TerminalHandle::new(channel.id(), session.handle());
-
-        let sender = self.repo_event_sender.clone();
+        // The session itself is started once the client asks for a shell
+        // or a command to run, after its pty and env requests
         let (session_event_tx, session_event_rx) = tokio::sync::mpsc::channel(100);
         self.session_event_sender = Some(session_event_tx);
-
-        let auth = self.auth.lock().await;
-        let is_op = match &self.public_key {
-            Some(key) => auth.is_op(key),
-            None => false,
-        };
-
-        spawn(async move {
-            sender
-                .send(SessionRepositoryEvent::NewSession(
-                    id,
-                    ssh_id,
-                    connect_username,
-                    is_op,
-                    key,
-                    terminal_handle,
-                    session_event_rx,
-                ))
-                .await
-                .unwrap();
-        });
+        self.session_event_receiver = Some(session_event_rx);
 
         Ok(true)
     }
@@ -101,9 +128,14 @@ impl Handler for ThinHandler {
         pk: &PublicKey,
     ) -> Result<Auth, Self::Error> {
         info!("Public key offered auth request for user {}", user);
+        self.close_if_ban_shown()?;
 
-        let mut auth = self.auth.lock().await;
-        if auth.is_trusted(pk) && !auth.check_bans(&user, &pk) {
+        if let Some(ban) = self.find_ban(user, Some(pk)).await {
+            return Ok(self.reject_banned(ban));
+        }
+
+        let auth = self.auth.lock().await;
+        if auth.is_trusted(pk) {
             return Ok(Auth::Accept);
         }
 
@@ -117,6 +149,12 @@ impl Handler for ThinHandler {
             "Public key auth request for user {} using key {:?}",
             user, pk
         );
+        self.close_if_ban_shown()?;
+
+        if let Some(ban) = self.find_ban(user, Some(pk)).await {
+            return Ok(self.reject_banned(ban));
+        }
+
         self.connect_username = String::from(user);
         self.public_key = Some(pk.clone());
         Ok(Auth::Accept)
@@ -124,9 +162,14 @@ impl Handler for ThinHandler {
 
     async fn auth_none(&mut self, user: &str) -> Result<Auth, Self::Error> {
         info!("None auth request for user {}", user);
+        self.close_if_ban_shown()?;
+
+        if let Some(ban) = self.find_ban(user, None).await {
+            return Ok(self.reject_banned(ban));
+        }
 
         let auth = self.auth.lock().await;
-        if auth.has_operators() {
+        if auth.has_operators() || auth.is_whitelist_enabled() {
             return Ok(Auth::Reject {
                 proceed_with_methods: Some(MethodSet::PUBLICKEY),
             });
@@ -142,6 +185,7 @@ impl Handler for ThinHandler {
             "Password auth request for user {} using credentials {}",
             user, password
         );
+        self.close_if_ban_shown()?;
         Ok(Auth::Reject {
             proceed_with_methods: Some(MethodSet::PUBLICKEY | MethodSet::NONE),
         })
@@ -155,11 +199,123 @@ impl Handler for ThinHandler {
         response: Option<Response<'async_trait>>,
     ) -> Result<Auth, Self::Error> {
         info!("Keyboard interactive auth request for user {}", user);
+        self.close_if_ban_shown()?;
+
+        if let Some(ban) = &self.ban {
+            // The reason is shown as the instructions of a challenge without
+            // any prompt, the answer to it is rejected with no method left
+            if response.is_some() {
+                self.ban_shown = true;
+                return Ok(Auth::Reject {
+                    proceed_with_methods: Some(MethodSet::empty()),
+                });
+            }
+
+            let mut text = String::from("You are banned from this server");
+            if let Some(reason) = &ban.reason {
+                text.push_str(&format!(": {}", reason));
+            }
+            text.push_str(&format!(
+                " (expires in {})",
+                humantime::format_duration(ban.remaining())
+            ));
+            return Ok(Auth::Partial {
+                name: "".into(),
+                instructions: text.into(),
+                prompts: vec![].into(),
+            });
+        }
+
         Ok(Auth::Reject {
             proceed_with_methods: Some(MethodSet::PUBLICKEY | MethodSet::NONE),
         })
     }
 
+    async fn shell_request(
+        &mut self,
+        channel: ChannelId,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        // The session has already been started on this connection
+        let event_rx = match self.session_event_receiver.take() {
+            Some(event_rx) => event_rx,
+            None => return Ok(()),
+        };
+
+        // Clients without a pty, e.g. `ssh -T`, are most likely scripts
+        let render_mode = if self.json_requested || !self.pty_requested {
+            RenderMode::Json
+        } else if self.plain_requested {
+            RenderMode::Plain
+        } else {
+            RenderMode::Terminal
+        };
+
+        info!(
+            "Starting a new session id={} in {:?} mode",
+            self.id, render_mode
+        );
+        session.channel_success(channel);
+
+        let event = SessionRepositoryEvent::NewSession(
+            self.id,
+            self.peer_addr,
+            String::from_utf8_lossy(session.remote_sshid()).to_string(),
+            self.connect_username.clone(),
+            self.is_op().await,
+            self.public_key.clone(),
+            render_mode,
+            TerminalHandle::new(channel, session.handle()),
+            event_rx,
+        );
+
+        let sender = self.repo_event_sender.clone();
+        spawn(async move {
+            sender.send(event).await.unwrap();
+        });
+
+        Ok(())
+    }
+
+    async fn exec_request(
+        &mut self,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        // The session has already been started on this connection
+        if self.session_event_receiver.take().is_none() {
+            return Ok(());
+        }
+        // Nothing is sent to an exec session once it has been started
+        self.session_event_sender = None;
+
+        let command = String::from_utf8_lossy(data).to_string();
+        info!(
+            "Executing a command for session id={}: {}",
+            self.id, command
+        );
+        session.channel_success(channel);
+
+        let event = SessionRepositoryEvent::Exec(
+            self.id,
+            self.peer_addr,
+            String::from_utf8_lossy(session.remote_sshid()).to_string(),
+            self.connect_username.clone(),
+            self.is_op().await,
+            self.public_key.clone(),
+            TerminalHandle::new(channel, session.handle()),
+            command,
+        );
+
+        let sender = self.repo_event_sender.clone();
+        spawn(async move {
+            sender.send(event).await.unwrap();
+        });
+
+        Ok(())
+    }
+
     #[allow(unused_variables)]
     async fn data(
         &mut self,
@@ -168,13 +324,14 @@ impl Handler for ThinHandler {
         session: &mut Session,
     ) -> Result<(), Self::Error> {
         let data = data.to_vec();
-        let sender = self
-            .session_event_sender
-            .clone()
-            .expect("Session event sender to be initialized during session creation");
+        // Exec sessions take no input once started
+        let sender = match self.session_event_sender.clone() {
+            Some(sender) => sender,
+            None => return Ok(()),
+        };
 
         tokio::spawn(async move {
-            sender.send(SessionEvent::Data(data)).await.unwrap();
+            let _ = sender.send(SessionEvent::Data(data)).await;
         });
 
         Ok(())
@@ -192,19 +349,24 @@ impl Handler for ThinHandler {
         modes: &[(Pty, u32)],
         session: &mut Session,
     ) -> Result<(), Self::Error> {
-        let sender = self
-            .session_event_sender
-            .clone()
-            .expect("Session event sender to be initialized during session creation");
+        self.pty_requested = true;
+        if term == "dumb" {
+            self.plain_requested = true;
+        }
+
+        // Exec sessions take no input once started
+        let sender = match self.session_event_sender.clone() {
+            Some(sender) => sender,
+            None => return Ok(()),
+        };
 
         tokio::spawn(async move {
-            sender
+            let _ = sender
                 .send(SessionEvent::WindowResize(
                     col_width as u16,
                     row_height as u16,
                 ))
-                .await
-                .unwrap();
+                .await;
         });
 
         Ok(())
@@ -220,19 +382,19 @@ impl Handler for ThinHandler {
         pix_height: u32,
         session: &mut Session,
     ) -> Result<(), Self::Error> {
-        let sender = self
-            .session_event_sender
-            .clone()
-            .expect("Session event sender to be initialized during session creation");
+        // Exec sessions take no input once started
+        let sender = match self.session_event_sender.clone() {
+            Some(sender) => sender,
+            None => return Ok(()),
+        };
 
         tokio::spawn(async move {
-            sender
+            let _ = sender
                 .send(SessionEvent::WindowResize(
                     col_width as u16,
                     row_height as u16,
                 ))
-                .await
-                .unwrap();
+                .await;
         });
 
         Ok(())
@@ -246,16 +408,27 @@ impl Handler for ThinHandler {
         variable_value: &str,
         session: &mut Session,
     ) -> Result<(), Self::Error> {
+        match variable_name {
+            "CHATD_MODE" if variable_value.eq_ignore_ascii_case("json") => {
+                self.json_requested = true;
+            }
+            // See https://no-color.org, any value but an empty one disables colors
+            "NO_COLOR" if !variable_value.is_empty() => self.plain_requested = true,
+            "CHATD_PLAIN" if variable_value == "1" => self.plain_requested = true,
+            _ => {}
+        }
+
         let name = variable_name.to_string();
         let value = variable_value.to_string();
 
-        let sender = self
-            .session_event_sender
-            .clone()
-            .expect("Session event sender to be initialized during session creation");
+        // Exec sessions take no input once started
+        let sender = match self.session_event_sender.clone() {
+            Some(sender) => sender,
+            None => return Ok(()),
+        };
 
         tokio::spawn(async move {
-            sender.send(SessionEvent::Env(name, value)).await.unwrap();
+            let _ = sender.send(SessionEvent::Env(name, value)).await;
         });
 
         Ok(())
@@ -272,11 +445,17 @@ impl Handler for ThinHandler {
 /// was before the session is opened (e.g. one of the authenticated methods rejected the connection)
 impl Drop for ThinHandler {
     fn drop(&mut self) {
+        // The receiver is still here if the session has never been started
+        if self.session_event_receiver.is_some() {
+            return;
+        }
+
         if let Some(sender) = &self.session_event_sender {
             info!("Clean up from disconnected session id={}", self.id);
             let sender = sender.clone();
+            // The session may have been closed by the server already
             tokio::spawn(async move {
-                sender.send(SessionEvent::Disconnect).await.unwrap();
+                let _ = sender.send(SessionEvent::Disconnect).await;
             });
         }
     }
@@ -1,4 +1,5 @@
 use std::fmt::Debug;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use log::{debug, info, warn};
@@ -8,16 +9,20 @@ use tokio::spawn;
 use tokio::sync::mpsc::{self, Receiver};
 use tokio::sync::{watch, Mutex};
 
+use crate::server::room::{RenderMode, User};
 use crate::server::session_workflow::*;
-use crate::server::terminal::keyboard_decoder;
+use crate::server::terminal::keyboard_decoder::{KeyInput, KeyboardDecoder};
 use crate::server::terminal::Terminal;
 use crate::server::terminal::TerminalHandle;
 use crate::server::ServerRoom;
+use crate::utils;
 
 type SessionId = usize;
+type SessionPeerAddr = Option<SocketAddr>;
 type SessionSshId = String;
 type SessionConnectUsername = String;
 type SessionIsOp = bool;
+type SessionExecCommand = String;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum SessionEvent {
@@ -30,25 +35,49 @@ pub enum SessionEvent {
 pub enum SessionRepositoryEvent {
     NewSession(
         SessionId,
+        SessionPeerAddr,
         SessionSshId,
         SessionConnectUsername,
         SessionIsOp,
         Option<PublicKey>,
+        RenderMode,
         TerminalHandle,
         Receiver<SessionEvent>,
     ),
+    Exec(
+        SessionId,
+        SessionPeerAddr,
+        SessionSshId,
+        SessionConnectUsername,
+        SessionIsOp,
+        Option<PublicKey>,
+        TerminalHandle,
+        SessionExecCommand,
+    ),
 }
 
 impl Debug for SessionRepositoryEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::NewSession(arg0, arg1, arg2, arg3, arg4, _arg5, _arg6) => f
+            Self::NewSession(arg0, arg1, arg2, arg3, arg4, arg5, arg6, _arg7, _arg8) => f
                 .debug_tuple("NewSession")
                 .field(arg0)
                 .field(arg1)
                 .field(arg2)
                 .field(arg3)
                 .field(arg4)
+                .field(arg5)
+                .field(arg6)
+                .finish(),
+            Self::Exec(arg0, arg1, arg2, arg3, arg4, arg5, _arg6, arg7) => f
+                .debug_tuple("Exec")
+                .field(arg0)
+                .field(arg1)
+                .field(arg2)
+                .field(arg3)
+                .field(arg4)
+                .field(arg5)
+                .field(arg7)
                 .finish(),
         }
     }
@@ -70,36 +99,121 @@ impl SessionRepository {
             match event {
                 SessionRepositoryEvent::NewSession(
                     id,
+                    peer_addr,
                     ssh_id,
                     username,
                     is_op,
                     pk,
+                    render_mode,
                     handle,
                     event_rx,
                 ) => {
                     let room = room.clone();
                     let mut terminal = Terminal::new(handle);
+                    terminal.set_render_mode(render_mode);
                     let (message_tx, message_rx) = mpsc::channel(100);
+                    let (disconnect_tx, disconnect_rx) = mpsc::channel(1);
 
                     spawn(async move {
                         {
                             let mut room = room.lock().await;
-                            let user = room.join(id, username, is_op, pk, ssh_id, message_tx).await;
+                            let mut user = User::new(id, username, ssh_id, peer_addr, pk, is_op);
+                            user.set_render_mode(render_mode);
+                            let user = room.join(user, message_tx, disconnect_tx).await;
                             terminal.set_prompt(&terminal.get_prompt(&user));
+                            terminal.input.load_history(room.load_input_history(&user));
+                            if let Err(err) = terminal.enable_bracketed_paste() {
+                                warn!("Failed to enable bracketed paste for id={id}: {err}");
+                            }
                         }
-                        Self::handle_session(id, room, terminal, event_rx, message_rx).await;
+                        Self::handle_session(
+                            id,
+                            room,
+                            terminal,
+                            event_rx,
+                            message_rx,
+                            disconnect_rx,
+                        )
+                        .await;
                     });
                 }
+                SessionRepositoryEvent::Exec(
+                    id,
+                    peer_addr,
+                    ssh_id,
+                    username,
+                    is_op,
+                    pk,
+                    handle,
+                    command,
+                ) => {
+                    let room = room.clone();
+                    let mut user = User::new(id, username, ssh_id, peer_addr, pk, is_op);
+                    user.set_render_mode(RenderMode::Plain);
+                    spawn(Self::handle_exec(room, user, handle, command));
+                }
             }
         }
     }
 
+    /// Runs a single command as a temporary, unannounced room member and
+    /// sends back everything the member received while running it. A user
+    /// who is also connected interactively runs it as that member instead.
+    async fn handle_exec(
+        room: Arc<Mutex<ServerRoom>>,
+        user: User,
+        mut handle: TerminalHandle,
+        command: SessionExecCommand,
+    ) {
+        let id = user.id;
+        let (message_tx, mut message_rx) = mpsc::channel(100);
+        let (disconnect_tx, _) = mpsc::channel(1);
+
+        {
+            let mut room = room.lock().await;
+            let attachment = room.attach_exec(&user, message_tx.clone());
+            let user = match &attachment {
+                Some(_) => {
+                    // Only the member holds the sender now, so that the
+                    // channel ends once they are detached
+                    drop(message_tx);
+                    room.find_member(&user.username).user.clone()
+                }
+                None => room.join_quietly(user, message_tx, disconnect_tx),
+            };
+            let mut terminal = Terminal::new(handle.clone());
+            let mut ctx = WorkflowContext::new(user);
+
+            let command_executor = CommandExecutor::default();
+            let mut exec_parser = ExecParser::new(command, command_executor);
+            exec_parser
+                .execute(&mut ctx, &mut terminal, &mut room)
+                .await;
+
+            match attachment {
+                Some(attachment) => room.detach_exec(attachment),
+                None => room.leave_quietly(&id),
+            }
+        }
+
+        // The member is gone, so the channel ends after the buffered messages
+        let mut output = String::new();
+        while let Some(msg) = message_rx.recv().await {
+            output.push_str(&utils::strip_ansi(&msg).replace('\r', ""));
+            output.push('\n');
+        }
+
+        handle.close_with_output(output, 0).await;
+        info!("Exec session id={id} is finished");
+    }
+
     async fn handle_session(
         id: SessionId,
         room: Arc<Mutex<ServerRoom>>,
         terminal: Terminal,
         event_rx: Receiver<SessionEvent>,
         message_rx: Receiver<String>,
+        disconnect_rx: Receiver<String>,
     ) {
         let (exit_tx, exit_rx) = watch::channel(());
         let terminal = Arc::new(Mutex::new(terminal));
@@ -110,13 +224,17 @@ impl SessionRepository {
             terminal.clone(),
             event_rx,
             exit_tx.clone(),
+            exit_rx.clone(),
         ));
 
         let message_handle = spawn(Self::process_message_events(
             id,
+            room,
             terminal,
             message_rx,
-            exit_rx.clone(),
+            disconnect_rx,
+            exit_tx,
+            exit_rx,
         ));
 
         let _ = session_handle.await;
@@ -131,36 +249,90 @@ impl SessionRepository {
         terminal: Arc<Mutex<Terminal>>,
         mut event_rx: Receiver<SessionEvent>,
         exit_tx: watch::Sender<()>,
+        mut exit_rx: watch::Receiver<()>,
     ) {
         info!("Session events processing task for id={id} is started");
 
-        while let Some(event) = event_rx.recv().await {
+        // Pastes longer than any input could be, even one made of the widest
+        // UTF-8 characters, are dropped
+        let max_paste_len = room.lock().await.config().input_max_len * 4;
+        let mut decoder = KeyboardDecoder::new(max_paste_len);
+
+        loop {
+            let event = tokio::select! {
+                _ = exit_rx.changed() => {
+                    info!("Session events processing task for id={id} aborted because session is disconnected");
+                    return;
+                }
+                event = event_rx.recv() => match event {
+                    Some(event) => event,
+                    None => return,
+                },
+            };
+
             match event {
                 SessionEvent::Data(data) => {
                     let mut room = room.lock().await;
                     let mut term = terminal.lock().await;
 
-                    let user = room.find_member_by_id(id).user.clone();
+                    // The member may have been disconnected while the event was queued
+                    let Some(user) = room.try_find_member_by_id(id).map(|m| m.user.clone()) else {
+                        return;
+                    };
+
+                    room.register_activity(id).await;
+                    if term.render_mode() == RenderMode::Json {
+                        Self::process_input_lines(id, &data, &mut term, &mut room).await;
+                        continue;
+                    }
+
                     let mut ctx = WorkflowContext::new(user);
 
-                    let codes = keyboard_decoder::decode_bytes_to_codes(&data);
-                    for code in codes {
-                        match code {
-                            KeyCode::Tab => {
+                    for input in decoder.decode(&data) {
+                        match input {
+                            KeyInput::Key(KeyCode::Tab) => {
+                                term.accept_search().unwrap();
                                 let mut autocomplete = Autocomplete::default();
                                 autocomplete.execute(&mut ctx, &mut term, &mut room).await;
                             }
-                            KeyCode::Enter => {
+                            KeyInput::Key(KeyCode::Enter) => {
+                                term.accept_search().unwrap();
                                 let command_executor = CommandExecutor::default();
                                 let command_parser = CommandParser::new(command_executor);
                                 let input_validator = InputValidator::new(command_parser);
                                 let mut rate_checker = InputRateChecker::new(input_validator);
                                 rate_checker.execute(&mut ctx, &mut term, &mut room).await;
                             }
-                            _ => {
+                            KeyInput::Key(code) => {
                                 let mut key_mapper = TerminalKeyMapper::new(code);
                                 key_mapper.execute(&mut ctx, &mut term, &mut room).await;
                             }
+                            KeyInput::Alt(code) => {
+                                let mut key_mapper = TerminalKeyMapper::with_alt(code);
+                                key_mapper.execute(&mut ctx, &mut term, &mut room).await;
+                            }
+                            KeyInput::Paste(text) => {
+                                term.accept_search().unwrap();
+                                term.input
+                                    .insert_before_cursor(text.trim_end_matches('\n').as_bytes());
+                                if !term.input.text().contains('\n') {
+                                    term.print_input_line().unwrap();
+                                    continue;
+                                }
+
+                                // Multi-line text can't be edited in the input line, so it
+                                // is sent right away as a single message
+                                let command_executor = CommandExecutor::default();
+                                let command_parser = CommandParser::new(command_executor);
+                                let input_validator = InputValidator::new(command_parser);
+                                let mut rate_checker = InputRateChecker::new(input_validator);
+                                rate_checker.execute(&mut ctx, &mut term, &mut room).await;
+
+                                // Nor can it be left there if the message was dropped
+                                if term.input.text().contains('\n') {
+                                    term.clear_input().unwrap();
+                                }
+                            }
                         }
                     }
                 }
@@ -168,7 +340,9 @@ impl SessionRepository {
                     let mut room = room.lock().await;
                     let mut term = terminal.lock().await;
 
-                    let user = room.find_member_by_id(id).user.clone();
+                    let Some(user) = room.try_find_member_by_id(id).map(|m| m.user.clone()) else {
+                        return;
+                    };
                     let mut ctx = WorkflowContext::new(user);
 
                     let command_executor = CommandExecutor::default();
@@ -191,10 +365,58 @@ impl SessionRepository {
         }
     }
 
+    /// Handles the input of a session without a terminal, where each line
+    /// is either a message or a command. Incomplete lines are kept until the
+    /// rest of them arrives.
+    async fn process_input_lines(
+        id: SessionId,
+        data: &[u8],
+        terminal: &mut Terminal,
+        room: &mut ServerRoom,
+    ) {
+        // A pty sends CR on Enter, while pipes use LF or CRLF
+        let is_line_end = |b: &u8| *b == b'\n' || *b == b'\r';
+        for chunk in data.split_inclusive(is_line_end) {
+            match chunk.split_last() {
+                Some((last, line)) if is_line_end(last) => {
+                    terminal.input.insert_before_cursor(line);
+                }
+                _ => {
+                    terminal.input.insert_before_cursor(chunk);
+                    continue;
+                }
+            }
+
+            // Blank lines, e.g. between CR and LF, do not count towards the rate limit
+            if terminal.input.text().trim().is_empty() {
+                terminal.input.clear();
+                continue;
+            }
+
+            // A command on a previous line may have disconnected the member
+            let Some(user) = room.try_find_member_by_id(id).map(|m| m.user.clone()) else {
+                return;
+            };
+            let mut ctx = WorkflowContext::new(user);
+
+            let command_executor = CommandExecutor::default();
+            let command_parser = CommandParser::new(command_executor);
+            let input_validator = InputValidator::new(command_parser);
+            let mut rate_checker = InputRateChecker::new(input_validator);
+            rate_checker.execute(&mut ctx, terminal, room).await;
+
+            // Dropped lines must not be glued to the next one
+            terminal.input.clear();
+        }
+    }
+
     async fn process_message_events(
         id: SessionId,
+        room: Arc<Mutex<ServerRoom>>,
         terminal: Arc<Mutex<Terminal>>,
         mut message_rx: Receiver<String>,
+        mut disconnect_rx: Receiver<String>,
+        exit_tx: watch::Sender<()>,
         mut exit_rx: watch::Receiver<()>,
     ) {
         info!("Render task for id={id} is started");
@@ -212,6 +434,23 @@ impl SessionRepository {
                 // Warning: This situation is uncommon and should not occur under normal circumstances.
                 warn!("Render task for id={id} finished its work");
             }
+            Some(reason) = disconnect_rx.recv() => {
+                {
+                    let mut terminal = terminal.lock().await;
+                    let _ = terminal.print_message(&reason);
+                    terminal.exit();
+                }
+
+                // Nothing renders the member's messages from now on, so they
+                // leave the room right away rather than once the connection
+                // is gone. The session task is stopped first so that it
+                // doesn't handle input of a member who is no longer there,
+                // and the terminal is unlocked first, as the session task
+                // locks the room before the terminal.
+                let _ = exit_tx.send(());
+                room.lock().await.leave(&id).await;
+                info!("Render task for id={id} closed the session on request");
+            }
         }
     }
 }
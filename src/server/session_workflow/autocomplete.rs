@@ -3,6 +3,8 @@ use async_trait::async_trait;
 use super::handler::WorkflowHandler;
 use super::WorkflowContext;
 
+use crate::server::auth::BanAttribute;
+use crate::server::room::{message, Theme, TimestampMode, User};
 use crate::server::terminal::Terminal;
 use crate::server::ServerRoom;
 
@@ -20,37 +22,32 @@ impl WorkflowHandler for Autocomplete {
         terminal: &mut Terminal,
         room: &mut ServerRoom,
     ) {
-        let input_str = terminal.input.to_string();
-        if input_str.trim().is_empty() {
+        // Repeated Tab cycles through the candidates, the second one also lists them
+        if terminal.input.cycle_completion() {
+            terminal.print_input_line().unwrap();
+            let completion = terminal.input.completion().unwrap();
+            if completion.tabs() == 2 && completion.candidates().len() > 1 {
+                let body = completion.candidates().join(" ");
+                let message = message::System::new(context.user.clone(), body);
+                room.send_message(message.into()).await;
+            }
             return;
         }
 
-        let mut iter = input_str.splitn(3, ' ');
-        let cmd = iter.next().unwrap_or(&input_str);
-        let name = iter.next().unwrap_or("").trim();
-
-        let cmd_end_pos = cmd.len();
-        let name_end_pos = cmd_end_pos + name.len() + 1;
+        let text = terminal.input.text();
         let cursor_pos = terminal.input.cursor_byte_pos();
+        let before_cursor = &text[..cursor_pos];
+        let word_start = before_cursor.rfind(' ').map_or(0, |pos| pos + 1);
 
-        if cmd.starts_with("/") && cursor_pos > 0 && cursor_pos <= cmd_end_pos {
-            let completed = room.commands().from_prefix(&cmd);
-            if let Some(command) = completed {
-                let cmd_bytes = command.cmd().as_bytes();
-                terminal.input.move_cursor_to(cmd_end_pos);
-                terminal.input.remove_last_word_before_cursor();
-                terminal.input.insert_before_cursor(cmd_bytes);
-                terminal.print_input_line().unwrap();
-            }
-        } else if !name.is_empty() && cursor_pos > cmd_end_pos + 1 && cursor_pos <= name_end_pos {
-            let completed = room.find_name_by_prefix(name, &context.user.username);
-            if let Some(username) = completed {
-                let name_bytes = username.as_bytes();
-                terminal.input.move_cursor_to(name_end_pos);
-                terminal.input.remove_last_word_before_cursor();
-                terminal.input.insert_before_cursor(name_bytes);
-                terminal.print_input_line().unwrap();
-            }
+        let candidates = find_candidates(
+            &before_cursor[word_start..],
+            &before_cursor[..word_start],
+            &context.user,
+            room,
+        );
+        if !candidates.is_empty() {
+            terminal.input.start_completion(word_start, candidates);
+            terminal.print_input_line().unwrap();
         }
     }
 
@@ -58,3 +55,57 @@ impl WorkflowHandler for Autocomplete {
         &mut self.next
     }
 }
+
+/// Finds the candidates to complete a word with, given the words before it
+fn find_candidates(word: &str, preceding: &str, user: &User, room: &ServerRoom) -> Vec<String> {
+    let words: Vec<&str> = preceding.split_whitespace().collect();
+
+    if words.is_empty() && word.starts_with('/') {
+        return room
+            .commands()
+            .from_prefix(word)
+            .iter()
+            .map(|cmd| cmd.cmd().to_string())
+            .collect();
+    }
+
+    // Mentions are completed anywhere in a message
+    if let Some(prefix) = word.strip_prefix('@') {
+        return room
+            .find_names_by_prefix(prefix, &user.username)
+            .into_iter()
+            .map(|name| format!("@{}", name))
+            .collect();
+    }
+
+    let Some(cmd) = words.first().filter(|w| w.starts_with('/')) else {
+        return vec![];
+    };
+
+    let values = match (*cmd, words.len()) {
+        ("/theme", 1) => Theme::all(),
+        ("/timestamp", 1) => TimestampMode::all(),
+        // Ban queries alternate attributes and durations, e.g. `name=bob 1h ip=::1 1d`
+        ("/ban" | "/unban", count) if count % 2 == 1 => {
+            let mut values: Vec<String> = BanAttribute::keys()
+                .iter()
+                .map(|key| format!("{}=", key))
+                .collect();
+            if count == 1 {
+                values.extend(room.find_names_by_prefix(word, &user.username));
+            }
+            values
+        }
+        // Replies and threads take a message id rather than a name
+        ("/re" | "/thread", 1) => vec![],
+        ("/edit" | "/delete", 1) => vec!["last".to_string()],
+        // The first argument of most commands is a user name
+        (_, 1) => return room.find_names_by_prefix(word, &user.username),
+        _ => vec![],
+    };
+
+    values
+        .into_iter()
+        .filter(|value| value.starts_with(word))
+        .collect()
+}
@@ -1,9 +1,18 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use russh_keys::key::PublicKey;
+use std::collections::BTreeSet;
 use std::io::Write;
+use std::time::Duration;
 
 use crate::server::auth::{BanAttribute, BanQuery};
-use crate::server::room::message::Message;
-use crate::server::room::{message, Command, Theme, TimestampMode, UserStatus};
+use crate::server::memo::Memo;
+use crate::server::room::message::{Message, MessageFormatter, MessageId};
+use crate::server::room::{
+    message, Channel, Command, MessageRef, RoomMember, Theme, TimestampMode, User, UserStatus,
+    WhitelistAction, DEFAULT_CHANNEL_NAME,
+};
+use crate::server::seen::SeenRecord;
 use crate::server::terminal::Terminal;
 use crate::server::ServerRoom;
 use crate::utils;
@@ -11,6 +20,9 @@ use crate::utils;
 use super::handler::WorkflowHandler;
 use super::WorkflowContext;
 
+/// Members idle for less than this are not marked as idle in `/users`
+const IDLE_DISPLAY_MIN: Duration = Duration::from_secs(60);
+
 #[derive(Default)]
 pub struct CommandExecutor {
     next: Option<Box<dyn WorkflowHandler>>,
@@ -41,7 +53,7 @@ impl WorkflowHandler for CommandExecutor {
                 let member = room.find_member_mut(username);
                 member.user.go_away(reason.to_string());
 
-                let message = message::Emote::new(
+                let message = message::Emote::system(
                     member.user.clone(),
                     format!("has gone away: \"{}\"", reason),
                 );
@@ -49,13 +61,10 @@ impl WorkflowHandler for CommandExecutor {
             }
             Command::Back => {
                 let member = room.find_member_mut(username);
-                if let UserStatus::Away {
-                    reason: _,
-                    since: _,
-                } = &member.user.status
-                {
+                if let UserStatus::Away { .. } = &member.user.status {
                     member.user.return_active();
-                    let message = message::Emote::new(member.user.clone(), "is back".to_string());
+                    let message =
+                        message::Emote::system(member.user.clone(), "is back".to_string());
                     room.send_message(message.into()).await;
                 }
             }
@@ -81,6 +90,18 @@ impl WorkflowHandler for CommandExecutor {
                     break 'label;
                 }
 
+                if !room
+                    .name_registry()
+                    .is_allowed(&new_name, user.fingerprint().as_deref())
+                {
+                    let message = message::Error::new(
+                        user,
+                        format!("\"{}\" name is registered to another key", new_name),
+                    );
+                    room.send_message(message.into()).await;
+                    break 'label;
+                }
+
                 let message = message::Announce::new(
                     user.clone(),
                     format!("user is now known as {}.", new_name),
@@ -100,6 +121,80 @@ impl WorkflowHandler for CommandExecutor {
                 room.remove_member(&old_name);
                 room.add_name(user_id, new_name);
             }
+            Command::Register => 'label: {
+                let Some(fingerprint) = user.fingerprint() else {
+                    let message = message::Error::new(
+                        user,
+                        "connect with a public key to register your name".to_string(),
+                    );
+                    room.send_message(message.into()).await;
+                    break 'label;
+                };
+
+                let message: Message = match room.name_registry().find(username) {
+                    Some(registration) if registration.fingerprint == fingerprint => {
+                        message::Error::new(user, "your name is already registered".to_string())
+                            .into()
+                    }
+                    Some(_) => message::Error::new(
+                        user,
+                        "your name is registered to another key".to_string(),
+                    )
+                    .into(),
+                    None => match room.name_registry_mut().register(username, &fingerprint) {
+                        Ok(None) => message::System::new(
+                            user,
+                            format!("\"{}\" name is now reserved for your key", username),
+                        )
+                        .into(),
+                        Ok(Some(replaced)) => message::System::new(
+                            user,
+                            format!(
+                                "\"{}\" name is now reserved for your key instead of \"{}\"",
+                                username, replaced
+                            ),
+                        )
+                        .into(),
+                        Err(err) => message::Error::new(user, format!("{:#}", err)).into(),
+                    },
+                };
+                room.send_message(message).await;
+            }
+            Command::Unregister(name) => 'label: {
+                let name = name.unwrap_or_else(|| username.clone());
+                let is_owner = match room.name_registry().find(&name) {
+                    Some(registration) => {
+                        Some(&registration.fingerprint) == user.fingerprint().as_ref()
+                    }
+                    None => {
+                        let message = message::Error::new(
+                            user,
+                            format!("\"{}\" name is not registered", name),
+                        );
+                        room.send_message(message.into()).await;
+                        break 'label;
+                    }
+                };
+
+                if !is_owner && !user.is_op {
+                    let message = message::Error::new(
+                        user,
+                        format!("\"{}\" name is registered to another key", name),
+                    );
+                    room.send_message(message.into()).await;
+                    break 'label;
+                }
+
+                let message: Message = match room.name_registry_mut().unregister(&name) {
+                    Ok(_) => message::System::new(
+                        user,
+                        format!("\"{}\" name is no longer reserved", name),
+                    )
+                    .into(),
+                    Err(err) => message::Error::new(user, format!("{:#}", err)).into(),
+                };
+                room.send_message(message).await;
+            }
             Command::Msg(to, msg) => 'label: {
                 let from = room.find_member(username).user.clone();
 
@@ -129,7 +224,7 @@ impl WorkflowHandler for CommandExecutor {
                         room.send_message(message.into()).await;
 
                         match status {
-                            UserStatus::Away { reason, since: _ } => {
+                            UserStatus::Away { reason, .. } => {
                                 let message = message::System::new(
                                     from.clone(),
                                     format!(
@@ -169,21 +264,101 @@ impl WorkflowHandler for CommandExecutor {
                 let message = message::Private::new(from, to, message_body);
                 room.send_message(message.into()).await;
             }
+            Command::Tell(to, body) => 'label: {
+                if user.is_muted {
+                    let member = room.find_member(username);
+                    let _ = member.send_user_is_muted_message().await;
+                    break 'label;
+                }
+
+                // The sender is stored with the memo so its name can't be
+                // taken to leave messages on behalf of someone else
+                let Some(from_fingerprint) = user.fingerprint() else {
+                    let message = message::Error::new(
+                        user,
+                        "you need to connect with a public key to leave messages".to_string(),
+                    );
+                    room.send_message(message.into()).await;
+                    break 'label;
+                };
+
+                let fingerprint = match resolve_fingerprint(room, &to) {
+                    Ok(fingerprint) => fingerprint,
+                    Err(err) => {
+                        let message = message::Error::new(user, err);
+                        room.send_message(message.into()).await;
+                        break 'label;
+                    }
+                };
+
+                // The recipient may be connected under another name
+                let recipient = room.members_iter().map(|(_, m)| &m.user).find(|u| {
+                    u.public_key
+                        .as_ref()
+                        .is_some_and(|key| key.fingerprint() == fingerprint)
+                });
+                if let Some(to) = recipient.cloned() {
+                    if to.id == user.id {
+                        let message =
+                            message::Error::new(user, "you can't message yourself".to_string());
+                        room.send_message(message.into()).await;
+                        break 'label;
+                    }
+
+                    room.find_member_mut(&to.username)
+                        .user
+                        .set_reply_to(user.id);
+                    let message = message::Private::new(user, to, body);
+                    room.send_message(message.into()).await;
+                    break 'label;
+                }
+
+                let memo = Memo {
+                    from: user.username.clone(),
+                    from_fingerprint,
+                    body,
+                    created_at: Utc::now(),
+                };
+                let message: Message = match room.leave_memo(&fingerprint, memo) {
+                    Ok(_) => message::System::new(
+                        user,
+                        format!(
+                            "Message for SHA256:{} will be delivered when they next join",
+                            fingerprint
+                        ),
+                    )
+                    .into(),
+                    Err(err) => message::Error::new(user, format!("{:#}", err)).into(),
+                };
+                room.send_message(message).await;
+            }
             Command::Users => {
                 let member = room.find_member(username);
                 let user = member.user.clone();
 
-                let mut usernames = room.names().values().collect::<Vec<&String>>();
-                usernames.sort_by_key(|a| a.to_lowercase());
+                let mut members = room
+                    .member_channel(username)
+                    .members()
+                    .iter()
+                    .filter_map(|id| room.try_get_name(id))
+                    .map(|name| room.find_member(name))
+                    .collect::<Vec<&RoomMember>>();
+                members.sort_by_key(|m| m.user.username.to_lowercase());
 
-                let colorized_names = usernames
+                let colorized_names = members
                     .iter()
-                    .map(|u| user.theme.style_username(u).to_string())
+                    .map(|m| {
+                        let name = user.theme.style_username(&m.user.username).to_string();
+                        match m.idle_duration() {
+                            idle if idle < IDLE_DISPLAY_MIN => name,
+                            idle => format!("{} (idle {})", name, format_idle(idle)),
+                        }
+                    })
                     .collect::<Vec<String>>();
 
                 let body = format!(
                     "{} connected: {}",
-                    room.names().len(),
+                    members.len(),
                     colorized_names.join(", ")
                 );
 
@@ -193,11 +368,20 @@ impl WorkflowHandler for CommandExecutor {
             Command::Whois(target_name) => {
                 let member = room.find_member(username);
                 let user = member.user.clone();
-                let message = match room
-                    .try_find_member(&target_name)
-                    .map(|member| &member.user)
-                {
-                    Some(target) => message::System::new(user, target.to_string()).into(),
+                let message = match room.try_find_member(&target_name) {
+                    Some(member) => {
+                        let target = &member.user;
+                        let mut body = target.to_string();
+                        body.push_str(&format!(
+                            "{} > idle: {}",
+                            utils::NEWLINE,
+                            format_idle(member.idle_duration())
+                        ));
+                        if let Some(addr) = target.peer_addr.filter(|_| user.is_op) {
+                            body.push_str(&format!("{} > ip: {}", utils::NEWLINE, addr.ip()));
+                        }
+                        message::System::new(user, body).into()
+                    }
                     None => message::Error::new(user, "user not found".to_string()).into(),
                 };
                 room.send_message(message).await;
@@ -268,6 +452,45 @@ impl WorkflowHandler for CommandExecutor {
                 );
                 room.send_message(message.into()).await;
             }
+            Command::Ids => {
+                let member = room.find_member_mut(username);
+                member.user.switch_ids_mode();
+                let message = message::System::new(
+                    member.user.clone(),
+                    match member.user.show_ids {
+                        true => "Message IDs are toggled ON",
+                        false => "Message IDs are toggled OFF",
+                    }
+                    .to_string(),
+                );
+                room.send_message(message.into()).await;
+            }
+            Command::Seen(name) => {
+                let message: Message = match room.try_find_member(&name) {
+                    Some(member) => {
+                        let body = match &member.user.status {
+                            UserStatus::Away { reason, .. } => {
+                                format!("{} is here, away: {}", name, reason)
+                            }
+                            UserStatus::Active => format!("{} is here", name),
+                        };
+                        message::System::new(user, body).into()
+                    }
+                    None => {
+                        let seen = room.seen_store();
+                        let record = match name.strip_prefix("SHA256:") {
+                            Some(fingerprint) => seen.find_by_fingerprint(fingerprint),
+                            None => seen.find_by_name(&name),
+                        };
+                        match record {
+                            Some(record) => message::System::new(user, format_seen(record)).into(),
+                            None => message::Error::new(user, "user has not been seen".to_string())
+                                .into(),
+                        }
+                    }
+                };
+                room.send_message(message).await;
+            }
             Command::Timestamp(mode) => {
                 let member = room.find_member_mut(username);
                 member.user.set_timestamp_mode(mode);
@@ -287,7 +510,7 @@ impl WorkflowHandler for CommandExecutor {
                 let member = room.find_member_mut(username);
                 let message = message::System::new(user, format!("Set theme: {}", theme));
 
-                member.user.theme = theme.into();
+                member.user.set_theme(theme);
                 terminal.set_prompt(&terminal.get_prompt(&member.user));
                 room.send_message(message.into()).await;
             }
@@ -471,6 +694,175 @@ impl WorkflowHandler for CommandExecutor {
                 let message = message::System::new(user, message_text);
                 room.send_message(message.into()).await;
             }
+            Command::Join(channel_name) => 'label: {
+                if room.member_channel(username).name() == &channel_name {
+                    let message =
+                        message::Error::new(user, format!("you are already in {}", channel_name));
+                    room.send_message(message.into()).await;
+                    break 'label;
+                }
+
+                room.join_channel(username, &channel_name).await;
+            }
+            Command::Part => 'label: {
+                if room.member_channel(username).is_default() {
+                    let message = message::Error::new(
+                        user,
+                        "you can't leave the lobby, use /exit instead".to_string(),
+                    );
+                    room.send_message(message.into()).await;
+                    break 'label;
+                }
+
+                room.join_channel(username, DEFAULT_CHANNEL_NAME).await;
+            }
+            Command::Rooms => {
+                let current = room.member_channel(username).name().clone();
+
+                let mut channels = room.channels().collect::<Vec<&Channel>>();
+                channels.sort_by_key(|c| c.name());
+
+                let mut buf = Vec::new();
+                write!(buf, "{} rooms:", channels.len()).unwrap();
+
+                for channel in channels {
+                    write!(
+                        buf,
+                        "{} {} {} ({} connected)",
+                        utils::NEWLINE,
+                        if channel.name() == &current { "*" } else { " " },
+                        channel.name(),
+                        channel.members().len()
+                    )
+                    .unwrap();
+                    if !channel.topic().is_empty() {
+                        write!(buf, ": {}", channel.topic()).unwrap();
+                    }
+                }
+
+                let message = message::System::new(user, String::from_utf8(buf).unwrap());
+                room.send_message(message.into()).await;
+            }
+            Command::Topic(topic) => 'label: {
+                let channel = room.member_channel(username);
+                let Some(topic) = topic else {
+                    let body = match channel.topic().is_empty() {
+                        true => format!("No topic is set for {}", channel.name()),
+                        false => format!("Topic of {}: {}", channel.name(), channel.topic()),
+                    };
+                    let message = message::System::new(user, body);
+                    room.send_message(message.into()).await;
+                    break 'label;
+                };
+
+                // Everyone joins the lobby, so only operators set its topic
+                if channel.is_default() && !user.is_op {
+                    let message = message::Error::new(
+                        user,
+                        "must be an operator to set the topic of the lobby".to_string(),
+                    );
+                    room.send_message(message.into()).await;
+                    break 'label;
+                }
+
+                room.member_channel_mut(username).set_topic(topic.clone());
+                let message = message::Announce::new(user, format!("set the topic: {}", topic));
+                room.send_message(message.into()).await;
+            }
+            Command::History(limit) => 'label: {
+                let channel = room.member_channel(username).name().clone();
+                let limit = limit.unwrap_or(room.config().history_len);
+
+                let messages = match room.load_history(&channel, limit) {
+                    Ok(messages) => messages,
+                    Err(err) => {
+                        let message = message::Error::new(user, format!("{:#}", err));
+                        room.send_message(message.into()).await;
+                        break 'label;
+                    }
+                };
+
+                if messages.is_empty() {
+                    let message =
+                        message::System::new(user, format!("No messages in {} yet", channel));
+                    room.send_message(message.into()).await;
+                    break 'label;
+                }
+
+                let member = room.find_member(username);
+                for message in messages {
+                    if member.send_message(message).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            Command::Re(id, body) => 'label: {
+                let channel = room.member_channel(username);
+                let quote = match channel.history().find(id).and_then(|m| m.quote()) {
+                    Some(quote) => quote,
+                    None => {
+                        let message =
+                            message::Error::new(user, format!("message #{} not found", id));
+                        room.send_message(message.into()).await;
+                        break 'label;
+                    }
+                };
+
+                room.find_member_mut(username)
+                    .update_last_sent_time(Utc::now());
+                let message = message::Public::reply(user, body, quote);
+                room.send_message(message.into()).await;
+            }
+            Command::Thread(id) => 'label: {
+                let messages = room.member_channel(username).history().thread(id);
+                if messages.is_empty() {
+                    let message = message::Error::new(user, format!("message #{} not found", id));
+                    room.send_message(message.into()).await;
+                    break 'label;
+                }
+
+                let member = room.find_member(username);
+                for message in messages {
+                    if member.send_message(message).await.is_err() {
+                        break;
+                    }
+                }
+            }
+            Command::Edit(target, body) => 'label: {
+                if user.is_muted {
+                    let member = room.find_member(username);
+                    let _ = member.send_user_is_muted_message().await;
+                    break 'label;
+                }
+
+                let id = match find_own_message(room, &user, target) {
+                    Ok(id) => id,
+                    Err(err) => {
+                        let message = message::Error::new(user, err);
+                        room.send_message(message.into()).await;
+                        break 'label;
+                    }
+                };
+
+                room.find_member_mut(username)
+                    .update_last_sent_time(Utc::now());
+                room.edit_message(&user, id, body).await;
+            }
+            Command::Delete(target) => 'label: {
+                if user.is_muted {
+                    let member = room.find_member(username);
+                    let _ = member.send_user_is_muted_message().await;
+                    break 'label;
+                }
+
+                match find_own_message(room, &user, target) {
+                    Ok(id) => room.delete_message(&user, id).await,
+                    Err(err) => {
+                        let message = message::Error::new(user, err);
+                        room.send_message(message.into()).await;
+                    }
+                }
+            }
             Command::Version => {
                 let message = message::System::new(user, format!("{}", env!("CARGO_PKG_VERSION")));
                 room.send_message(message.into()).await;
@@ -522,7 +914,8 @@ impl WorkflowHandler for CommandExecutor {
             }
             Command::Motd(new_motd) => 'label: {
                 if new_motd.is_none() {
-                    let message = message::System::new(user, room.motd().clone());
+                    let motd = room.member_channel(username).motd().clone();
+                    let message = message::System::new(user, motd);
                     room.send_message(message.into()).await;
                     break 'label;
                 }
@@ -536,14 +929,15 @@ impl WorkflowHandler for CommandExecutor {
                     break 'label;
                 }
 
-                room.set_motd(new_motd.unwrap());
+                let channel = room.member_channel_mut(username);
+                channel.set_motd(new_motd.unwrap());
 
                 let message = message::Announce::new(
                     user.clone(),
                     format!(
                         "set new message of the day: {}-> {}",
                         utils::NEWLINE,
-                        room.motd()
+                        channel.motd()
                     ),
                 );
                 room.send_message(message.into()).await;
@@ -555,14 +949,15 @@ impl WorkflowHandler for CommandExecutor {
                     break 'label;
                 }
 
-                match room.try_find_member_mut(&target_username) {
+                match room.try_find_member(&target_username) {
                     None => {
                         let message = message::Error::new(user, "user not found".to_string());
                         room.send_message(message.into()).await;
                         break 'label;
                     }
-                    Some(_) => {
-                        terminal.exit();
+                    Some(target) => {
+                        let reason = format!("You were kicked from the server by {}", username);
+                        target.disconnect(reason).await;
 
                         let message = message::Announce::new(
                             user,
@@ -586,25 +981,30 @@ impl WorkflowHandler for CommandExecutor {
                     break 'label;
                 }
 
-                let mut messages: Vec<Message> = vec![];
+                // Names of online members matching the query, disconnected once all bans are applied
+                let mut targets: BTreeSet<String> = BTreeSet::new();
+                let query = query.unwrap();
 
-                match query.unwrap() {
-                    BanQuery::Single { name, duration } => {
+                match &query {
+                    BanQuery::Single {
+                        name,
+                        duration,
+                        reason,
+                    } => {
                         match room
-                            .try_find_member(&name)
+                            .try_find_member(name)
                             .filter(|member| member.user.public_key.is_some())
                         {
                             Some(member) => {
-                                room.auth().lock().await.ban_fingerprint(
-                                    &member.user.public_key.as_ref().unwrap().fingerprint(),
-                                    duration,
-                                );
-                                terminal.exit();
-                                let message = message::Announce::new(
-                                    user.clone(),
-                                    format!("banned {} from the server", member.user.username),
+                                let fingerprint =
+                                    member.user.public_key.as_ref().unwrap().fingerprint();
+                                room.auth().lock().await.ban(
+                                    BanAttribute::Fingerprint(fingerprint),
+                                    *duration,
+                                    username,
+                                    reason.clone(),
                                 );
-                                messages.push(message.into());
+                                targets.insert(member.user.username.clone());
                             }
                             None => {
                                 let message =
@@ -614,54 +1014,73 @@ impl WorkflowHandler for CommandExecutor {
                             }
                         }
                     }
-                    BanQuery::Multiple(items) => {
+                    BanQuery::Multiple { items, reason } => {
                         for item in items {
-                            match item.attribute {
-                                BanAttribute::Name(name) => {
-                                    room.auth().lock().await.ban_username(&name, item.duration);
+                            room.auth().lock().await.ban(
+                                item.attribute.clone(),
+                                item.duration,
+                                username,
+                                reason.clone(),
+                            );
 
+                            match &item.attribute {
+                                BanAttribute::Name(name) => {
                                     for (_, member) in room.members_iter_mut() {
-                                        if member.user.username.eq(&name) {
-                                            terminal.exit();
-                                            let message = message::Announce::new(
-                                                user.clone(),
-                                                format!("banned {} from the server", name),
-                                            );
-                                            messages.push(message.into());
+                                        if member.user.username.eq(name) {
+                                            targets.insert(member.user.username.clone());
                                         }
                                     }
                                 }
                                 BanAttribute::Fingerprint(fingerprint) => {
-                                    room.auth()
-                                        .lock()
-                                        .await
-                                        .ban_fingerprint(&fingerprint, item.duration);
-
                                     for (_, member) in room.members_iter_mut() {
                                         if let Some(key) = &member.user.public_key {
-                                            if key.fingerprint().eq(&fingerprint) {
-                                                terminal.exit();
-                                                let message = message::Announce::new(
-                                                    user.clone(),
-                                                    format!(
-                                                        "banned {} from the server",
-                                                        member.user.username
-                                                    ),
-                                                );
-                                                messages.push(message.into());
+                                            if key.fingerprint().eq(fingerprint) {
+                                                targets.insert(member.user.username.clone());
+                                            }
+                                        }
+                                    }
+                                }
+                                BanAttribute::Ip(net) => {
+                                    for (_, member) in room.members_iter_mut() {
+                                        if let Some(addr) = &member.user.peer_addr {
+                                            if net.contains(&addr.ip()) {
+                                                targets.insert(member.user.username.clone());
                                             }
                                         }
                                     }
                                 }
-                                BanAttribute::Ip(_) => todo!(),
                             }
                         }
                     }
                 }
 
+                let mut messages: Vec<Message> = vec![];
+
+                let reason = match &query {
+                    BanQuery::Single { reason, .. } | BanQuery::Multiple { reason, .. } => reason,
+                };
+
+                for target_username in &targets {
+                    let mut text = format!("You were banned from the server by {}", username);
+                    if let Some(reason) = reason {
+                        text.push_str(&format!(": {}", reason));
+                    }
+                    room.find_member(target_username).disconnect(text).await;
+
+                    let message = message::Announce::new(
+                        user.clone(),
+                        format!("banned {} from the server", target_username),
+                    );
+                    messages.push(message.into());
+                }
+
                 let message = message::System::new(
                     user,
-                    "Banning is complete. Offline users were silently banned.".to_string(),
+                    match targets.is_empty() {
+                        true => "Banning is complete. No online users matched the query.",
+                        false => "Banning is complete. Offline users were silently banned.",
+                    }
+                    .to_string(),
                 );
                 messages.push(message.into());
 
@@ -669,6 +1088,266 @@ impl WorkflowHandler for CommandExecutor {
                     room.send_message(message).await;
                 }
             }
+            Command::Unban(query) => 'label: {
+                if !user.is_op {
+                    let message = message::Error::new(user, "must be an operator".to_string());
+                    room.send_message(message.into()).await;
+                    break 'label;
+                }
+
+                let attributes = query
+                    .split_whitespace()
+                    .map(|part| part.parse::<BanAttribute>())
+                    .collect::<Result<Vec<BanAttribute>, &str>>();
+
+                let attributes = match attributes {
+                    Ok(attributes) => attributes,
+                    Err(err) => {
+                        let message = message::Error::new(user, err.to_string());
+                        room.send_message(message.into()).await;
+                        break 'label;
+                    }
+                };
+
+                let mut buf = Vec::new();
+                write!(buf, "Unbanned:").unwrap();
+
+                for attribute in attributes {
+                    let found = room.auth().lock().await.unban(&attribute);
+                    write!(
+                        buf,
+                        "{} \"{}\"{}",
+                        utils::NEWLINE,
+                        attribute,
+                        if found { "" } else { " (was not banned)" }
+                    )
+                    .unwrap();
+                }
+
+                let message = message::System::new(user, String::from_utf8(buf).unwrap());
+                room.send_message(message.into()).await;
+            }
+            Command::Op(ref target_username) | Command::Deop(ref target_username) => 'label: {
+                if !user.is_op {
+                    let message = message::Error::new(user, "must be an operator".to_string());
+                    room.send_message(message.into()).await;
+                    break 'label;
+                }
+
+                let grant = matches!(command, Command::Op(_));
+
+                let key = match room.try_find_member(target_username) {
+                    None => {
+                        let message = message::Error::new(user, "user not found".to_string());
+                        room.send_message(message.into()).await;
+                        break 'label;
+                    }
+                    Some(target) => match &target.user.public_key {
+                        Some(key) => key.clone(),
+                        None => {
+                            let message = message::Error::new(
+                                user,
+                                "user has no public key to identify them by".to_string(),
+                            );
+                            room.send_message(message.into()).await;
+                            break 'label;
+                        }
+                    },
+                };
+
+                let changed = match grant {
+                    true => room.auth().lock().await.add_operator(&key, target_username),
+                    false => room.auth().lock().await.remove_operator(&key),
+                };
+
+                if !changed {
+                    let message = message::Error::new(
+                        user,
+                        match grant {
+                            true => "user is already an operator",
+                            false => "user is not an operator",
+                        }
+                        .to_string(),
+                    );
+                    room.send_message(message.into()).await;
+                    break 'label;
+                }
+
+                // Every session connected with the same key shares the operator status
+                let mut targets = vec![];
+                for (_, member) in room.members_iter_mut() {
+                    if member.user.public_key.as_ref() == Some(&key) {
+                        member.user.is_op = grant;
+                        targets.push(member.user.clone());
+                    }
+                }
+
+                for target in targets {
+                    let message = message::System::new(
+                        target,
+                        match grant {
+                            true => format!("You were made an operator by {}", username),
+                            false => format!("Your operator rights were revoked by {}", username),
+                        },
+                    );
+                    room.send_message(message.into()).await;
+                }
+
+                let message = message::System::new(
+                    user,
+                    match grant {
+                        true => format!("Made {} an operator", target_username),
+                        false => format!("Revoked operator rights from {}", target_username),
+                    },
+                );
+                room.send_message(message.into()).await;
+            }
+            Command::Whitelist(action) => 'label: {
+                if !user.is_op {
+                    let message = message::Error::new(user, "must be an operator".to_string());
+                    room.send_message(message.into()).await;
+                    break 'label;
+                }
+
+                let message: Message = match action {
+                    WhitelistAction::List => {
+                        let auth = room.auth().lock().await;
+                        let keys = auth.trusted_keys();
+
+                        let mut buf = Vec::new();
+                        write!(
+                            buf,
+                            "Whitelist is {}, {} trusted keys:",
+                            match auth.is_whitelist_enabled() {
+                                true => "on",
+                                false => "off",
+                            },
+                            keys.len()
+                        )
+                        .unwrap();
+
+                        for key in keys {
+                            write!(buf, "{} SHA256:{}", utils::NEWLINE, key.fingerprint()).unwrap();
+
+                            let mut names = room
+                                .members_iter()
+                                .filter(|(_, m)| m.user.public_key.as_ref() == Some(key))
+                                .map(|(_, m)| m.user.username.as_str())
+                                .collect::<Vec<&str>>();
+                            names.sort();
+
+                            if !names.is_empty() {
+                                write!(buf, " ({})", names.join(", ")).unwrap();
+                            }
+                        }
+
+                        message::System::new(user, String::from_utf8(buf).unwrap()).into()
+                    }
+                    WhitelistAction::Add(arg) => match resolve_public_key(room, &arg) {
+                        Ok((key, name)) => {
+                            let comment = name.unwrap_or_else(|| format!("added by {}", username));
+                            match room.auth().lock().await.add_trusted_key(&key, &comment) {
+                                true => message::System::new(
+                                    user,
+                                    format!("Added {} to the whitelist", arg),
+                                )
+                                .into(),
+                                false => message::Error::new(
+                                    user,
+                                    "key is already whitelisted".to_string(),
+                                )
+                                .into(),
+                            }
+                        }
+                        Err(err) => message::Error::new(user, err).into(),
+                    },
+                    WhitelistAction::Remove(arg) => {
+                        let fingerprint = arg.strip_prefix("SHA256:").unwrap_or(&arg);
+                        let key = room
+                            .auth()
+                            .lock()
+                            .await
+                            .trusted_keys()
+                            .iter()
+                            .find(|key| key.fingerprint() == fingerprint)
+                            .cloned();
+
+                        let key = match key {
+                            Some(key) => Ok(key),
+                            None => resolve_public_key(room, &arg).map(|(key, _)| key),
+                        };
+
+                        match key {
+                            Ok(key) => match room.auth().lock().await.remove_trusted_key(&key) {
+                                true => message::System::new(
+                                    user,
+                                    format!("Removed {} from the whitelist", arg),
+                                )
+                                .into(),
+                                false => {
+                                    message::Error::new(user, "key is not whitelisted".to_string())
+                                        .into()
+                                }
+                            },
+                            Err(err) => message::Error::new(user, err).into(),
+                        }
+                    }
+                    WhitelistAction::On | WhitelistAction::Off => {
+                        let enabled = action == WhitelistAction::On;
+                        room.auth().lock().await.set_whitelist_enabled(enabled);
+                        message::System::new(
+                            user,
+                            match enabled {
+                                true => "Whitelist is on, only trusted keys may connect",
+                                false => "Whitelist is off, anyone may connect",
+                            }
+                            .to_string(),
+                        )
+                        .into()
+                    }
+                    WhitelistAction::Import(path) => match utils::ssh::read_public_keys(&path) {
+                        Ok(keys) => {
+                            let comment = format!("imported by {}", username);
+                            let mut auth = room.auth().lock().await;
+                            let added = keys
+                                .iter()
+                                .filter(|key| auth.add_trusted_key(key, &comment))
+                                .count();
+
+                            message::System::new(
+                                user,
+                                format!(
+                                    "Imported {} keys from {} ({} already whitelisted)",
+                                    added,
+                                    path,
+                                    keys.len() - added
+                                ),
+                            )
+                            .into()
+                        }
+                        Err(err) => {
+                            message::Error::new(user, format!("failed to read {}: {}", path, err))
+                                .into()
+                        }
+                    },
+                };
+                room.send_message(message).await;
+            }
+            Command::Reload => 'label: {
+                if !user.is_op {
+                    let message = message::Error::new(user, "must be an operator".to_string());
+                    room.send_message(message.into()).await;
+                    break 'label;
+                }
+
+                let message: Message = match room.reload().await {
+                    Ok(summary) => {
+                        message::System::new(user, format!("Reloaded: {}", summary)).into()
+                    }
+                    Err(err) => message::Error::new(user, format!("{:#}", err)).into(),
+                };
+                room.send_message(message).await;
+            }
             Command::Banned => 'label: {
                 if !user.is_op {
                     let message = message::Error::new(user, "must be an operator".to_string());
@@ -676,16 +1355,23 @@ impl WorkflowHandler for CommandExecutor {
                     break 'label;
                 }
 
-                let (names, fingerprints) = room.auth().lock().await.banned();
                 let mut buf = Vec::new();
                 write!(buf, "Banned:").unwrap();
 
-                for name in names {
-                    write!(buf, "{} \"name={}\"", utils::NEWLINE, name).unwrap();
-                }
+                for ban in room.auth().lock().await.banned() {
+                    write!(
+                        buf,
+                        "{} \"{}\" (expires in {}, by {})",
+                        utils::NEWLINE,
+                        ban.attribute,
+                        humantime::format_duration(ban.remaining()),
+                        ban.banned_by
+                    )
+                    .unwrap();
 
-                for fingerprint in fingerprints {
-                    write!(buf, "{} \"fingerprint={}\"", utils::NEWLINE, fingerprint).unwrap();
+                    if let Some(reason) = &ban.reason {
+                        write!(buf, ": {}", reason).unwrap();
+                    }
                 }
 
                 let message = message::System::new(user, String::from_utf8(buf).unwrap());
@@ -698,3 +1384,103 @@ impl WorkflowHandler for CommandExecutor {
         &mut self.next
     }
 }
+
+/// Formats an idle duration with minute precision, or seconds if shorter.
+fn format_idle(idle: Duration) -> String {
+    let secs = match idle.as_secs() {
+        secs if secs < 60 => secs,
+        secs => secs - secs % 60,
+    };
+    humantime::format_duration(Duration::from_secs(secs)).to_string()
+}
+
+/// Finds a message in the history of the user's channel that they may
+/// change, i.e. one they sent, or any message sent by a user for operators.
+/// Messages restored from the history store can only be changed by operators,
+/// as there is no telling who sent them.
+fn find_own_message(
+    room: &ServerRoom,
+    user: &User,
+    target: MessageRef,
+) -> Result<MessageId, String> {
+    let history = room.member_channel(&user.username).history();
+    let message = match target {
+        MessageRef::Id(id) => history
+            .find(id)
+            .ok_or_else(|| format!("message #{} not found", id))?,
+        MessageRef::Last => history
+            .last_sent_by(user)
+            .ok_or_else(|| "you have no recent messages in this room".to_string())?,
+    };
+
+    match message.sender() {
+        Some(_) if message.is_sent_by(user) || user.is_op => {
+            Ok(message.get_id().unwrap_or_default())
+        }
+        Some(_) => Err("you can only change your own messages".to_string()),
+        None => Err("only messages sent by users can be changed".to_string()),
+    }
+}
+
+/// Formats a last-seen record, e.g. `alice: last seen 3h ago, was away: lunch`
+fn format_seen(record: &SeenRecord) -> String {
+    let ago = |time: DateTime<Utc>| format_idle((Utc::now() - time).to_std().unwrap_or_default());
+    let mut text = format!("{}: last seen {} ago", record.username, ago(record.left_at));
+    if let Some(time) = record.last_sent_at {
+        text.push_str(&format!(", last message {} ago", ago(time)));
+    }
+    if let Some(reason) = &record.away {
+        text.push_str(&format!(", was away: {}", reason));
+    }
+    text
+}
+
+/// Resolves the name of a connected user, a name registered to a key or a
+/// key fingerprint, with or without the `SHA256:` prefix, to a key
+/// fingerprint.
+fn resolve_fingerprint(room: &ServerRoom, arg: &str) -> Result<String, String> {
+    if let Some(member) = room.try_find_member(arg) {
+        return match &member.user.public_key {
+            Some(key) => Ok(key.fingerprint()),
+            None => Err("user has no public key to leave messages for".to_string()),
+        };
+    }
+
+    // Unlike the last-seen names, registered ones can't be taken by others
+    if let Some(registration) = room.name_registry().find(arg) {
+        return Ok(registration.fingerprint.clone());
+    }
+
+    // SHA256 fingerprints are 43 characters of unpadded base64
+    let fingerprint = arg.strip_prefix("SHA256:").unwrap_or(arg);
+    let is_fingerprint = fingerprint.len() == 43
+        && fingerprint
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/');
+    match is_fingerprint {
+        true => Ok(fingerprint.to_string()),
+        false => Err("user is not registered, use their key fingerprint instead".to_string()),
+    }
+}
+
+/// Resolves a user name or a public key in the `authorized_keys` format to a
+/// public key. The name of the connected user or the key comment is returned
+/// alongside the key if there is one.
+fn resolve_public_key(room: &ServerRoom, arg: &str) -> Result<(PublicKey, Option<String>), String> {
+    if let Some(member) = room.try_find_member(arg) {
+        return match &member.user.public_key {
+            Some(key) => Ok((key.clone(), Some(member.user.username.clone()))),
+            None => Err("user has no public key to identify them by".to_string()),
+        };
+    }
+
+    let parts = arg.split_whitespace().collect::<Vec<&str>>();
+    for (idx, part) in parts.iter().enumerate() {
+        if let Ok(key) = russh_keys::parse_public_key_base64(part) {
+            let comment = parts[idx + 1..].join(" ");
+            return Ok((key, Some(comment).filter(|c| !c.is_empty())));
+        }
+    }
+
+    Err("user not found and no valid public key given".to_string())
+}
@@ -50,6 +50,7 @@ impl WorkflowHandler for CommandParser {
             }
             Err(err) => {
                 terminal.input.push_to_history();
+                room.save_input_history(&user, &input_str);
                 terminal.clear_input().unwrap();
                 let message = message::Command::new(user.clone(), input_str);
                 room.send_message(message.into()).await;
@@ -58,6 +59,7 @@ impl WorkflowHandler for CommandParser {
             }
             Ok(command) => {
                 terminal.input.push_to_history();
+                room.save_input_history(&user, &input_str);
                 terminal.clear_input().unwrap();
                 let message = message::Command::new(user.clone(), input_str);
                 room.send_message(message.into()).await;
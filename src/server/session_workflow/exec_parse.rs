@@ -0,0 +1,104 @@
+use async_trait::async_trait;
+use chrono::Utc;
+
+use crate::server::room::{message, Command};
+use crate::server::terminal::Terminal;
+use crate::server::ServerRoom;
+
+use super::handler::{into_next, WorkflowHandler};
+use super::WorkflowContext;
+
+/// Commands that can be run without an interactive terminal, e.g. `ssh chat.host users`
+const EXEC_COMMANDS: [&str; 5] = ["users", "whois", "motd", "msg", "say"];
+
+pub struct ExecParser {
+    command_line: String,
+    next: Option<Box<dyn WorkflowHandler>>,
+}
+
+impl ExecParser {
+    pub fn new(command_line: String, next: impl WorkflowHandler + 'static) -> Self {
+        Self {
+            command_line,
+            next: into_next(next),
+        }
+    }
+}
+
+#[async_trait]
+impl WorkflowHandler for ExecParser {
+    #[allow(unused_variables)]
+    async fn handle(
+        &mut self,
+        context: &mut WorkflowContext,
+        terminal: &mut Terminal,
+        room: &mut ServerRoom,
+    ) {
+        let user = context.user.clone();
+        let line = self.command_line.trim();
+        let (name, args) = line.split_once(' ').unwrap_or((line, ""));
+        let name = name.trim_start_matches('/');
+
+        if line.len() > room.config().input_max_len {
+            let message =
+                message::Error::new(user, "message dropped. Input is too long".to_string());
+            room.send_message(message.into()).await;
+            return;
+        }
+
+        if let Err(remaining) = room.check_exec_ratelimit(&user) {
+            let body = format!(
+                "rate limit exceeded. Command dropped. Next allowed in {}",
+                humantime::format_duration(remaining)
+            );
+            let message = message::Error::new(user, body);
+            room.send_message(message.into()).await;
+            return;
+        }
+
+        if !EXEC_COMMANDS.contains(&name) {
+            let message = message::Error::new(
+                user,
+                format!(
+                    "unsupported command, expected one of: {}",
+                    EXEC_COMMANDS.join(", ")
+                ),
+            );
+            room.send_message(message.into()).await;
+            return;
+        }
+
+        if (name == "say" || name == "msg") && room.is_muted(&user) {
+            let member = room.find_member(&user.username);
+            let _ = member.send_user_is_muted_message().await;
+            return;
+        }
+
+        if name == "say" {
+            let body = args.trim();
+            if body.is_empty() {
+                let message = message::Error::new(user, "message is empty".to_string());
+                room.send_message(message.into()).await;
+                return;
+            }
+
+            room.find_member_mut(&user.username)
+                .update_last_sent_time(Utc::now());
+            let message = message::Public::new(user, body.to_string());
+            room.send_message(message.into()).await;
+            return;
+        }
+
+        match format!("/{} {}", name, args).trim_end().parse::<Command>() {
+            Ok(command) => context.command = Some(command),
+            Err(err) => {
+                let message = message::Error::new(user, format!("{}", err));
+                room.send_message(message.into()).await;
+            }
+        }
+    }
+
+    fn next(&mut self) -> &mut Option<Box<dyn WorkflowHandler>> {
+        &mut self.next
+    }
+}
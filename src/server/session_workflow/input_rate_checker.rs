@@ -2,7 +2,7 @@ use async_trait::async_trait;
 
 use crate::server::room::message;
 use crate::server::terminal::Terminal;
-use crate::server::{ratelimit, ServerRoom};
+use crate::server::ServerRoom;
 
 use super::handler::{into_next, WorkflowHandler};
 use super::WorkflowContext;
@@ -29,14 +29,7 @@ impl WorkflowHandler for InputRateChecker {
         terminal: &mut Terminal,
         room: &mut ServerRoom,
     ) {
-        let error = format!(
-            "User {} MUST have a rate-limit within a server room",
-            context.user.username
-        );
-
-        let rl = room.get_ratelimit(context.user.id).expect(error.as_str());
-
-        if let Err(remaining) = ratelimit::check(rl) {
+        if let Err(remaining) = room.check_ratelimit(&context.user.username) {
             let body = format!(
                 "rate limit exceeded. Message dropped. Next allowed in {}",
                 humantime::format_duration(remaining)
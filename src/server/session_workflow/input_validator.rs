@@ -7,8 +7,6 @@ use crate::server::room::message;
 use crate::server::terminal::Terminal;
 use crate::server::ServerRoom;
 
-const INPUT_MAX_LEN: usize = 1024;
-
 #[derive(Default)]
 pub struct InputValidator {
     next: Option<Box<dyn WorkflowHandler>>,
@@ -36,7 +34,7 @@ impl WorkflowHandler for InputValidator {
             self.next = None;
         }
 
-        if input_str.len() > INPUT_MAX_LEN {
+        if input_str.len() > room.config().input_max_len {
             let message = message::Error::new(
                 context.user.clone(),
                 "message dropped. Input is too long".to_string(),
@@ -3,6 +3,7 @@ mod command_exec;
 mod command_parse;
 mod context;
 mod env_parse;
+mod exec_parse;
 mod handler;
 mod input_rate_checker;
 mod input_validator;
@@ -13,6 +14,7 @@ pub use command_exec::CommandExecutor;
 pub use command_parse::CommandParser;
 pub use context::WorkflowContext;
 pub use env_parse::EnvParser;
+pub use exec_parse::ExecParser;
 pub use handler::WorkflowHandler;
 pub use input_rate_checker::InputRateChecker;
 pub use input_validator::InputValidator;
@@ -1,7 +1,7 @@
 use async_trait::async_trait;
 use terminal_keycode::KeyCode;
 
-use crate::server::terminal::Terminal;
+use crate::server::terminal::{SearchDirection, Terminal};
 use crate::server::ServerRoom;
 
 use super::handler::WorkflowHandler;
@@ -9,12 +9,26 @@ use super::WorkflowContext;
 
 pub struct TerminalKeyMapper {
     key: KeyCode,
+    alt: bool,
     next: Option<Box<dyn WorkflowHandler>>,
 }
 
 impl TerminalKeyMapper {
     pub fn new(key: KeyCode) -> Self {
-        Self { key, next: None }
+        Self {
+            key,
+            alt: false,
+            next: None,
+        }
+    }
+
+    /// Maps a key pressed along with Alt (Meta), e.g. Alt-b
+    pub fn with_alt(key: KeyCode) -> Self {
+        Self {
+            key,
+            alt: true,
+            next: None,
+        }
     }
 }
 
@@ -27,6 +41,57 @@ impl WorkflowHandler for TerminalKeyMapper {
         terminal: &mut Terminal,
         room: &mut ServerRoom,
     ) {
+        if terminal.input.is_searching() {
+            match self.key {
+                KeyCode::CtrlR if !self.alt => {
+                    terminal.input.search_history(SearchDirection::Reverse);
+                    terminal.print_input_line().unwrap();
+                    return;
+                }
+                KeyCode::CtrlS if !self.alt => {
+                    terminal.input.search_history(SearchDirection::Forward);
+                    terminal.print_input_line().unwrap();
+                    return;
+                }
+                KeyCode::CtrlG | KeyCode::Escape => {
+                    terminal.input.cancel_search();
+                    terminal.print_input_line().unwrap();
+                    return;
+                }
+                KeyCode::Backspace if !self.alt => {
+                    terminal.input.search_remove_last_char();
+                    terminal.print_input_line().unwrap();
+                    return;
+                }
+                KeyCode::Char(_) | KeyCode::Space if !self.alt => {
+                    terminal.input.search_insert(&self.key.bytes());
+                    terminal.print_input_line().unwrap();
+                    return;
+                }
+                // Any other key ends the search and edits the matched input
+                _ => terminal.input.accept_search(),
+            }
+        }
+
+        if self.alt {
+            match self.key {
+                KeyCode::Char('b') => {
+                    terminal.input.move_cursor_prev_word();
+                    terminal.print_input_line().unwrap();
+                }
+                KeyCode::Char('f') => {
+                    terminal.input.move_cursor_next_word();
+                    terminal.print_input_line().unwrap();
+                }
+                KeyCode::Char('d') => {
+                    terminal.input.remove_next_word_after_cursor();
+                    terminal.print_input_line().unwrap();
+                }
+                _ => {}
+            }
+            return;
+        }
+
         match self.key {
             KeyCode::Backspace => {
                 terminal.input.remove_before_cursor();
@@ -40,7 +105,28 @@ impl WorkflowHandler for TerminalKeyMapper {
                 terminal.input.move_cursor_end();
                 terminal.print_input_line().unwrap();
             }
-            KeyCode::CtrlD => todo!(),
+            KeyCode::CtrlD if terminal.input.char_count() == 0 => {
+                terminal.exit();
+            }
+            KeyCode::CtrlD | KeyCode::Delete => {
+                terminal.input.remove_at_cursor();
+                terminal.print_input_line().unwrap();
+            }
+            KeyCode::CtrlL => {
+                terminal.clear_screen().unwrap();
+            }
+            KeyCode::CtrlT => {
+                terminal.input.transpose_chars();
+                terminal.print_input_line().unwrap();
+            }
+            KeyCode::CtrlR => {
+                terminal.input.search_history(SearchDirection::Reverse);
+                terminal.print_input_line().unwrap();
+            }
+            KeyCode::CtrlS => {
+                terminal.input.search_history(SearchDirection::Forward);
+                terminal.print_input_line().unwrap();
+            }
             KeyCode::CtrlW => {
                 terminal.input.remove_last_word_before_cursor();
                 terminal.print_input_line().unwrap();
@@ -35,6 +35,26 @@ impl TerminalHandle {
 
         self.closed = true;
     }
+
+    /// Sends the output of a non-interactive command along with its exit
+    /// status and closes the channel.
+    pub async fn close_with_output(&mut self, output: String, exit_status: u32) {
+        let channel_id = self.channel_id;
+        if let Err(err) = self.handle.data(channel_id, output.into()).await {
+            error!(
+                "[channel {}] Failed to send data to the handle: {:?}",
+                channel_id, err
+            );
+        }
+
+        let _ = self
+            .handle
+            .exit_status_request(channel_id, exit_status)
+            .await;
+        let _ = self.handle.eof(channel_id).await;
+        let _ = self.handle.close(channel_id).await;
+        self.closed = true;
+    }
 }
 
 // The crossterm backend writes to the terminal handle.
@@ -2,12 +2,14 @@ use crate::utils;
 
 use super::input_history::InputHistory;
 use std::fmt::Display;
+use std::ops::Range;
 use unicode_segmentation::UnicodeSegmentation;
 
-const MAX_HISTORY_SIZE: usize = 20;
+/// Number of inputs kept in the history of each user
+pub const MAX_HISTORY_SIZE: usize = 20;
 
 // Struct representing user input state with cursor position
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, PartialEq)]
 struct InputState {
     text: String,           // String representing user input
     char_count: usize,      // Number of characters in the text
@@ -16,12 +18,66 @@ struct InputState {
     cursor_byte_pos: usize,
 }
 
+// Direction of an incremental history search
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchDirection {
+    Reverse, // From newer inputs to older ones
+    Forward, // From older inputs to newer ones
+}
+
+// Struct representing an incremental search through the input history
+#[derive(Clone, Debug)]
+pub struct HistorySearch {
+    query: String,              // Text to look for in the history
+    direction: SearchDirection, // Direction to look for the next match in
+    index: Option<usize>,       // History index of the current match
+    match_len: usize,           // Length of the current match in bytes
+    failed: bool,               // Whether the last lookup found nothing
+    original: InputState,       // Input to restore if the search is cancelled
+}
+
+impl HistorySearch {
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn direction(&self) -> SearchDirection {
+        self.direction
+    }
+
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+}
+
+// Struct representing the candidates of a completion cycled through with Tab
+#[derive(Clone, Debug)]
+pub struct Completion {
+    candidates: Vec<String>, // Candidates to replace the completed word with
+    index: usize,            // Index of the candidate currently in the input
+    start: usize,            // Byte position where the completed word starts
+    tabs: usize,             // Number of Tab presses in a row
+    state: InputState,       // Input as it was left by the last completion
+}
+
+impl Completion {
+    pub fn candidates(&self) -> &[String] {
+        &self.candidates
+    }
+
+    pub fn tabs(&self) -> usize {
+        self.tabs
+    }
+}
+
 // Struct representing user input with snapshot capability and input history
 #[derive(Clone, Debug, Default)]
 pub struct TerminalInput {
     state: InputState,                                   // Current input state
     snapshot: Option<InputState>,                        // Snapshot of previous state
     history: InputHistory<InputState, MAX_HISTORY_SIZE>, // Records the history of inputs made by the user
+    search: Option<HistorySearch>,                       // Incremental history search in progress
+    completion: Option<Completion>,                      // Tab completion in progress
 }
 
 impl Display for TerminalInput {
@@ -109,23 +165,33 @@ impl TerminalInput {
         self.state.cursor_byte_pos = self.state.text.len();
     }
 
-    // Move cursor to the given byte position
-    pub fn move_cursor_to(&mut self, pos: usize) {
-        if pos <= self.bytes().len() {
-            self.state.cursor_byte_pos = pos;
-            self.calc_new_cursor_char_pos();
-        }
+    // Move cursor to the start of the current or previous word
+    pub fn move_cursor_prev_word(&mut self) {
+        let graphemes: Vec<&str> = self.state.text.graphemes(true).collect();
+        self.state.cursor_char_pos = prev_word_start(&graphemes, self.state.cursor_char_pos);
+        self.calc_new_cursor_byte_pos();
+    }
+
+    // Move cursor to the end of the current or next word
+    pub fn move_cursor_next_word(&mut self) {
+        let graphemes: Vec<&str> = self.state.text.graphemes(true).collect();
+        self.state.cursor_char_pos = next_word_end(&graphemes, self.state.cursor_char_pos);
+        self.calc_new_cursor_byte_pos();
     }
 
     // Insert text before cursor position and update cursor
     pub fn insert_before_cursor(&mut self, bytes: &[u8]) {
-        let insert_text = &String::from_utf8_lossy(bytes);
+        // Control characters other than line breaks are never inserted
+        let insert_text = String::from_utf8_lossy(bytes)
+            .chars()
+            .filter(|c| *c == '\n' || !c.is_control())
+            .collect::<String>();
         self.state
             .text
-            .insert_str(self.state.cursor_byte_pos, insert_text);
+            .insert_str(self.state.cursor_byte_pos, &insert_text);
 
         let graphemes = self.state.text.graphemes(true).collect::<Vec<&str>>();
-        let new_cursor_byte_pos = self.state.cursor_byte_pos + bytes.len();
+        let new_cursor_byte_pos = self.state.cursor_byte_pos + insert_text.len();
 
         self.state.char_count = graphemes.len();
         self.state.cursor_byte_pos = new_cursor_byte_pos;
@@ -150,6 +216,58 @@ impl TerminalInput {
         self.state.display_width = utils::display_width(&self.state.text);
     }
 
+    // Remove character at cursor position
+    pub fn remove_at_cursor(&mut self) {
+        if self.state.cursor_char_pos >= self.state.char_count {
+            return; // Nothing to remove if cursor is at end
+        }
+
+        let graphemes: Vec<&str> = self.state.text.graphemes(true).collect();
+        let remove_len = graphemes[self.state.cursor_char_pos].len();
+        let start = self.state.cursor_byte_pos;
+
+        self.state.text.drain(start..start + remove_len);
+        self.state.char_count -= 1;
+        self.state.display_width = utils::display_width(&self.state.text);
+    }
+
+    // Swap the characters before and at cursor position, then move cursor
+    // forward. At the end of line, the last two characters are swapped.
+    pub fn transpose_chars(&mut self) {
+        let char_count = self.state.char_count;
+        if char_count < 2 || self.state.cursor_char_pos == 0 {
+            return;
+        }
+
+        let pos = self.state.cursor_char_pos.min(char_count - 1);
+        let mut graphemes: Vec<&str> = self.state.text.graphemes(true).collect();
+        graphemes.swap(pos - 1, pos);
+        self.state.text = graphemes.concat();
+
+        self.state.cursor_char_pos = pos + 1;
+        self.calc_new_cursor_byte_pos();
+    }
+
+    // Remove text from cursor position to the end of the current or next word
+    pub fn remove_next_word_after_cursor(&mut self) {
+        let prev = self.state.clone();
+        let graphemes: Vec<&str> = self.state.text.graphemes(true).collect();
+        let word_end = next_word_end(&graphemes, self.state.cursor_char_pos);
+        let word_end = char_to_byte_pos(&graphemes, word_end);
+
+        let drained = self
+            .state
+            .text
+            .drain(self.state.cursor_byte_pos..word_end)
+            .count();
+        if drained > 0 {
+            self.make_snapshot_from(prev);
+            let total_char_count = self.state.text.graphemes(true).count();
+            self.state.char_count = total_char_count;
+            self.state.display_width = utils::display_width(&self.state.text);
+        }
+    }
+
     // Remove last word before cursor position
     pub fn remove_last_word_before_cursor(&mut self) {
         let prev = self.state.clone();
@@ -206,6 +324,20 @@ impl TerminalInput {
         }
     }
 
+    // Pushes previously made inputs to the input history, oldest first
+    pub fn load_history(&mut self, inputs: Vec<String>) {
+        for text in inputs {
+            let state = InputState {
+                char_count: text.graphemes(true).count(),
+                display_width: utils::display_width(&text),
+                cursor_char_pos: text.graphemes(true).count(),
+                cursor_byte_pos: text.len(),
+                text,
+            };
+            self.history.push(state);
+        }
+    }
+
     // Pushes the current state to the input history
     pub fn push_to_history(&mut self) {
         self.history.push(self.state.clone());
@@ -234,6 +366,186 @@ impl TerminalInput {
         }
     }
 
+    // Get the incremental history search in progress, if any
+    pub fn search(&self) -> Option<&HistorySearch> {
+        self.search.as_ref()
+    }
+
+    // Check whether an incremental history search is in progress
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    // Get the byte range of the search query within the matched input
+    pub fn search_match(&self) -> Option<Range<usize>> {
+        let search = self.search.as_ref()?;
+        search.index?;
+        let start = self.state.cursor_byte_pos;
+        Some(start..start + search.match_len)
+    }
+
+    // Starts an incremental history search in the given direction. If a search
+    // is already in progress, looks for the next match past the current one
+    pub fn search_history(&mut self, direction: SearchDirection) {
+        let Some(search) = &mut self.search else {
+            self.search = Some(HistorySearch {
+                query: String::new(),
+                direction,
+                index: None,
+                match_len: 0,
+                failed: false,
+                original: self.state.clone(),
+            });
+            return;
+        };
+
+        search.direction = direction;
+        let start = match (search.index, direction) {
+            (Some(index), SearchDirection::Reverse) => index.checked_sub(1),
+            (Some(index), SearchDirection::Forward) => Some(index + 1),
+            (None, _) => search_start(self.history.len(), direction),
+        };
+        self.find_search_match(start);
+    }
+
+    // Appends text to the search query and looks for a match from the current one
+    pub fn search_insert(&mut self, bytes: &[u8]) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+
+        search.query.push_str(&String::from_utf8_lossy(bytes));
+        let start = search
+            .index
+            .or(search_start(self.history.len(), search.direction));
+        self.find_search_match(start);
+    }
+
+    // Removes the last character of the search query and looks for a match again
+    pub fn search_remove_last_char(&mut self) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+
+        if let Some((pos, _)) = search.query.grapheme_indices(true).next_back() {
+            search.query.truncate(pos);
+        }
+
+        if search.query.is_empty() {
+            self.state = search.original.clone();
+            search.index = None;
+            search.failed = false;
+            return;
+        }
+
+        let start = search_start(self.history.len(), search.direction);
+        self.find_search_match(start);
+    }
+
+    // Ends the search, keeping the matched input for editing
+    pub fn accept_search(&mut self) {
+        self.search = None;
+    }
+
+    // Ends the search, restoring the input it was started with
+    pub fn cancel_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.state = search.original;
+        }
+    }
+
+    // Looks for the search query in the history starting from the given index.
+    // The current match is kept if there is no other one
+    fn find_search_match(&mut self, start: Option<usize>) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+
+        let indices: Box<dyn Iterator<Item = usize>> = match (start, search.direction) {
+            (None, _) => Box::new(std::iter::empty()),
+            (Some(start), SearchDirection::Reverse) => Box::new((0..=start).rev()),
+            (Some(start), SearchDirection::Forward) => Box::new(start..self.history.len()),
+        };
+
+        for index in indices {
+            let Some(entry) = self.history.get(index) else {
+                continue;
+            };
+            let found = match search.direction {
+                SearchDirection::Reverse => entry.text.rfind(&search.query),
+                SearchDirection::Forward => entry.text.find(&search.query),
+            };
+            if let Some(pos) = found {
+                self.state = entry.clone();
+                self.state.cursor_byte_pos = pos;
+                search.index = Some(index);
+                search.match_len = search.query.len();
+                search.failed = false;
+                self.calc_new_cursor_char_pos();
+                return;
+            }
+        }
+
+        search.failed = true;
+    }
+
+    // Get the Tab completion in progress, if any
+    pub fn completion(&self) -> Option<&Completion> {
+        self.completion.as_ref()
+    }
+
+    // Replaces the text from the given byte position up to cursor position
+    // with the first of the candidates and starts cycling through them
+    pub fn start_completion(&mut self, start: usize, candidates: Vec<String>) {
+        let Some(first) = candidates.first() else {
+            self.completion = None;
+            return;
+        };
+
+        self.replace_before_cursor(start, &first.clone());
+        self.completion = Some(Completion {
+            candidates,
+            index: 0,
+            start,
+            tabs: 1,
+            state: self.state.clone(),
+        });
+    }
+
+    // Replaces the last completed candidate with the next one. Returns false
+    // if the input was changed since the last completion, which ends the cycle
+    pub fn cycle_completion(&mut self) -> bool {
+        let Some(completion) = &mut self.completion else {
+            return false;
+        };
+        if completion.state != self.state {
+            self.completion = None;
+            return false;
+        }
+
+        completion.index = (completion.index + 1) % completion.candidates.len();
+        completion.tabs += 1;
+        let start = completion.start;
+        let candidate = completion.candidates[completion.index].clone();
+
+        self.replace_before_cursor(start, &candidate);
+        if let Some(completion) = &mut self.completion {
+            completion.state = self.state.clone();
+        }
+        true
+    }
+
+    // Replace the text from the given byte position up to cursor position
+    fn replace_before_cursor(&mut self, start: usize, text: &str) {
+        self.state
+            .text
+            .replace_range(start..self.state.cursor_byte_pos, text);
+        self.state.cursor_byte_pos = start + text.len();
+        self.state.char_count = self.state.text.graphemes(true).count();
+        self.state.display_width = utils::display_width(&self.state.text);
+        self.calc_new_cursor_char_pos();
+    }
+
     // Create a snapshot of current state
     fn make_snapshot(&mut self) {
         self.snapshot = Some(self.state.clone());
@@ -262,6 +574,38 @@ impl TerminalInput {
     }
 }
 
+// Find the character position where the word before the given one starts
+fn prev_word_start(graphemes: &[&str], char_pos: usize) -> usize {
+    let mut pos = char_pos;
+    while pos > 0 && graphemes[pos - 1] == " " {
+        pos -= 1;
+    }
+    while pos > 0 && graphemes[pos - 1] != " " {
+        pos -= 1;
+    }
+    pos
+}
+
+// Find the character position where the word after the given one ends
+fn next_word_end(graphemes: &[&str], char_pos: usize) -> usize {
+    let mut pos = char_pos;
+    while pos < graphemes.len() && graphemes[pos] == " " {
+        pos += 1;
+    }
+    while pos < graphemes.len() && graphemes[pos] != " " {
+        pos += 1;
+    }
+    pos
+}
+
+// Find the history index to start a new search from
+fn search_start(history_len: usize, direction: SearchDirection) -> Option<usize> {
+    match direction {
+        SearchDirection::Reverse => history_len.checked_sub(1),
+        SearchDirection::Forward => Some(0),
+    }
+}
+
 fn char_to_byte_pos(graphemes: &Vec<&str>, char_pos: usize) -> usize {
     graphemes.iter().take(char_pos).map(|g| g.len()).sum()
 }
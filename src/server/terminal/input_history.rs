@@ -11,6 +11,14 @@ impl<T: Default + Clone, const N: usize> InputHistory<T, N> {
         self.nav_index
     }
 
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.history.get(index)
+    }
+
     pub fn push(&mut self, item: T) {
         self.history.push_back(item);
         self.nav_index = None; // Reset navigation index when a new command is added
@@ -1,5 +1,79 @@
 use terminal_keycode::{Decoder, KeyCode};
 
+/// Sent by terminals in bracketed paste mode before and after pasted text
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Input decoded from the bytes sent by a terminal
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyInput {
+    Key(KeyCode),
+    // Key pressed along with Alt (Meta), which terminals send prefixed with Escape
+    Alt(KeyCode),
+    // Text pasted at once, with line breaks normalized to `\n` and tabs to spaces
+    Paste(String),
+}
+
+/// Decodes the terminal input of a session. Pasted text may arrive in
+/// several chunks, so it is kept until the end of the paste is received.
+#[derive(Debug)]
+pub struct KeyboardDecoder {
+    paste: Option<Vec<u8>>,
+    max_paste_len: usize,
+    // Set once the paste in progress is too long, the rest of it is skipped
+    paste_dropped: bool,
+}
+
+impl KeyboardDecoder {
+    /// Pastes longer than `max_paste_len` bytes are dropped.
+    pub fn new(max_paste_len: usize) -> Self {
+        Self {
+            paste: None,
+            max_paste_len,
+            paste_dropped: false,
+        }
+    }
+
+    pub fn decode(&mut self, bytes: &[u8]) -> Vec<KeyInput> {
+        let mut inputs = vec![];
+        let mut bytes = bytes.to_vec();
+        loop {
+            match self.paste.take() {
+                Some(mut paste) => {
+                    // Only the new bytes are searched, along with the ones
+                    // before them the end of the paste may start in
+                    let from = paste.len().saturating_sub(PASTE_END.len() - 1);
+                    paste.extend_from_slice(&bytes);
+                    let Some(end) = find(&paste[from..], PASTE_END).map(|end| from + end) else {
+                        if paste.len() > self.max_paste_len {
+                            let skip = paste.len() - (PASTE_END.len() - 1);
+                            paste.drain(..skip);
+                            self.paste_dropped = true;
+                        }
+                        self.paste = Some(paste);
+                        break;
+                    };
+                    bytes = paste.split_off(end + PASTE_END.len());
+                    paste.truncate(end);
+                    if !std::mem::take(&mut self.paste_dropped) {
+                        inputs.push(KeyInput::Paste(normalize_paste(&paste)));
+                    }
+                }
+                None => {
+                    let Some(start) = find(&bytes, PASTE_START) else {
+                        inputs.extend(decode_keys(&bytes));
+                        break;
+                    };
+                    inputs.extend(decode_keys(&bytes[..start]));
+                    bytes = bytes.split_off(start + PASTE_START.len());
+                    self.paste = Some(vec![]);
+                }
+            }
+        }
+        inputs
+    }
+}
+
 pub fn decode_bytes_to_codes(bytes: &[u8]) -> Vec<KeyCode> {
     let mut decoder = Decoder::new();
     let mut codes = vec![];
@@ -10,3 +84,35 @@ pub fn decode_bytes_to_codes(bytes: &[u8]) -> Vec<KeyCode> {
     }
     codes
 }
+
+fn decode_keys(bytes: &[u8]) -> Vec<KeyInput> {
+    let mut inputs = vec![];
+    let mut codes = decode_bytes_to_codes(bytes).into_iter().peekable();
+    while let Some(code) = codes.next() {
+        match (code, codes.peek()) {
+            (KeyCode::Escape, Some(KeyCode::Char(_))) => {
+                inputs.push(KeyInput::Alt(codes.next().unwrap()));
+            }
+            (code, _) => inputs.push(KeyInput::Key(code)),
+        }
+    }
+    inputs
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// Line breaks are kept, every other control character is dropped so pasted
+// escape sequences can't reach the terminals of other users
+fn normalize_paste(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .replace('\t', "    ")
+        .chars()
+        .filter(|c| *c == '\n' || !c.is_control())
+        .collect()
+}
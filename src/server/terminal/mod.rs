@@ -6,4 +6,5 @@ mod terminal;
 pub mod keyboard_decoder;
 
 pub use handle::TerminalHandle;
+pub use input::{SearchDirection, MAX_HISTORY_SIZE};
 pub use terminal::Terminal;
@@ -1,20 +1,22 @@
 use crossterm::cursor;
+use crossterm::event::{DisableBracketedPaste, EnableBracketedPaste};
 use crossterm::queue;
-use crossterm::style;
+use crossterm::style::{self, Stylize};
 use crossterm::terminal::{Clear, ClearType};
 use std::io::Write;
 use unicode_segmentation::UnicodeSegmentation;
 
-use crate::server::room::User;
+use crate::server::room::{RenderMode, User};
 use crate::utils;
 use crate::utils::display_width;
 
 use super::handle::TerminalHandle;
-use super::input::TerminalInput;
+use super::input::{SearchDirection, TerminalInput};
 
 #[derive(Clone)]
 pub struct Terminal {
     pub input: TerminalInput,
+    render_mode: RenderMode,
     prompt: String,
     prompt_display_width: u16,
     handle: TerminalHandle,
@@ -25,12 +27,14 @@ pub struct Terminal {
     cursor_y: u16,
     input_end_x: u16,
     input_end_y: u16,
+    plain_line_width: usize,
 }
 
 impl Terminal {
     pub fn new(handle: TerminalHandle) -> Self {
         Self {
             handle,
+            render_mode: Default::default(),
             prompt: String::new(),
             prompt_display_width: 0,
             input: Default::default(),
@@ -41,9 +45,18 @@ impl Terminal {
             cursor_y: 0,
             input_end_x: 0,
             input_end_y: 0,
+            plain_line_width: 0,
         }
     }
 
+    pub fn render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+    }
+
     pub fn set_size(&mut self, width: u16, height: u16) {
         self.term_width = width;
         self.term_height = height;
@@ -66,11 +79,65 @@ impl Terminal {
         Ok(())
     }
 
+    /// Clears the screen and prints the prompt with the input at its top
+    pub fn clear_screen(&mut self) -> Result<(), anyhow::Error> {
+        match self.render_mode {
+            RenderMode::Terminal => {
+                queue!(self.handle, Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+                self.cursor_x = 0;
+                self.cursor_y = 0;
+                self.input_end_x = 0;
+                self.input_end_y = 0;
+            }
+            // Plain terminals can't be cleared, the input is moved to a new line instead
+            RenderMode::Plain => {
+                queue!(self.handle, style::Print(utils::NEWLINE))?;
+                self.plain_line_width = 0;
+            }
+            RenderMode::Json => return Ok(()),
+        }
+        self.print_input_line()
+    }
+
+    /// Ends the history search in progress, if any, keeping the matched input
+    pub fn accept_search(&mut self) -> Result<(), anyhow::Error> {
+        if self.input.is_searching() {
+            self.input.accept_search();
+            self.print_input_line()?;
+        }
+        Ok(())
+    }
+
+    /// Asks the terminal to mark pasted text, so that its line breaks are
+    /// not taken for Enter presses.
+    pub fn enable_bracketed_paste(&mut self) -> Result<(), anyhow::Error> {
+        if self.render_mode == RenderMode::Terminal {
+            queue!(self.handle, EnableBracketedPaste)?;
+            self.handle.flush()?;
+        }
+        Ok(())
+    }
+
     pub fn exit(&mut self) {
+        if self.render_mode == RenderMode::Terminal {
+            let _ = queue!(self.handle, DisableBracketedPaste);
+            let _ = self.handle.flush();
+        }
         self.handle.close();
     }
 
     pub fn print_input_line(&mut self) -> Result<(), anyhow::Error> {
+        match self.render_mode {
+            // There is no prompt, nor the input echo without a terminal
+            RenderMode::Json => return Ok(()),
+            RenderMode::Plain => {
+                self.queue_write_plain_input_line()?;
+                self.handle.flush()?;
+                return Ok(());
+            }
+            RenderMode::Terminal => {}
+        }
+
         self.queue_prompt_cleanup()?;
         self.queue_write_prompt()?;
         self.queue_write_input()?;
@@ -81,6 +148,22 @@ impl Terminal {
     }
 
     pub fn print_message(&mut self, msg: &str) -> Result<(), anyhow::Error> {
+        match self.render_mode {
+            RenderMode::Json => {
+                queue!(self.handle, style::Print(msg), style::Print("\n"))?;
+                self.handle.flush()?;
+                return Ok(());
+            }
+            RenderMode::Plain => {
+                self.queue_plain_cleanup()?;
+                self.queue_write_message(msg)?;
+                self.queue_write_plain_input_line()?;
+                self.handle.flush()?;
+                return Ok(());
+            }
+            RenderMode::Terminal => {}
+        }
+
         self.queue_prompt_cleanup()?;
         self.queue_write_message(msg)?;
         self.queue_write_prompt()?;
@@ -121,23 +204,98 @@ impl Terminal {
         Ok(())
     }
 
+    /// Blanks out the input line with spaces, as plain terminals may not
+    /// support escape sequences to clear it.
+    fn queue_plain_cleanup(&mut self) -> Result<(), anyhow::Error> {
+        queue!(
+            self.handle,
+            style::Print("\r"),
+            style::Print(" ".repeat(self.plain_line_width)),
+            style::Print("\r")
+        )?;
+        self.plain_line_width = 0;
+        Ok(())
+    }
+
+    /// Rewrites the prompt and the input from the start of the line. The text
+    /// before the cursor is written once more to leave the cursor in place.
+    fn queue_write_plain_input_line(&mut self) -> Result<(), anyhow::Error> {
+        let (prompt, prompt_width) = self.active_prompt();
+        let line_width = prompt_width as usize + self.input.display_width();
+        let padding = self.plain_line_width.saturating_sub(line_width);
+        let before_cursor = &self.input.text()[..self.input.cursor_byte_pos()];
+        queue!(
+            self.handle,
+            style::Print("\r"),
+            style::Print(&prompt),
+            style::Print(&self.input),
+            style::Print(" ".repeat(padding)),
+            style::Print("\r"),
+            style::Print(&prompt),
+            style::Print(before_cursor)
+        )?;
+        self.plain_line_width = line_width;
+        Ok(())
+    }
+
     fn queue_write_message(&mut self, msg: &str) -> Result<(), anyhow::Error> {
+        // Bare line feeds, e.g. in pasted text, would not return the cursor
+        // to the first column
+        let msg = msg
+            .split('\n')
+            .map(|line| line.trim_matches('\r'))
+            .collect::<Vec<_>>()
+            .join(utils::NEWLINE);
         queue!(self.handle, style::Print(msg), style::Print(utils::NEWLINE))?;
         Ok(())
     }
 
     fn queue_write_prompt(&mut self) -> Result<(), anyhow::Error> {
-        queue!(self.handle, style::Print(&self.prompt))?;
-        self.advance_cursor_pos(self.prompt_display_width);
+        let (prompt, prompt_width) = self.active_prompt();
+        queue!(self.handle, style::Print(prompt))?;
+        self.advance_cursor_pos(prompt_width);
         Ok(())
     }
 
     fn queue_write_input(&mut self) -> Result<(), anyhow::Error> {
-        queue!(self.handle, style::Print(&self.input))?;
+        match self.input.search_match() {
+            // Highlight the text matching the history search query
+            Some(range) => {
+                let text = self.input.text();
+                queue!(
+                    self.handle,
+                    style::Print(&text[..range.start]),
+                    style::PrintStyledContent(text[range.clone()].negative()),
+                    style::Print(&text[range.end..])
+                )?;
+            }
+            None => queue!(self.handle, style::Print(&self.input))?,
+        }
         self.advance_cursor_pos(self.input.display_width() as u16);
         Ok(())
     }
 
+    /// Returns the prompt to show before the input along with its width. It
+    /// is replaced by the search status while searching through the history.
+    fn active_prompt(&self) -> (String, u16) {
+        let Some(search) = self.input.search() else {
+            return (self.prompt.clone(), self.prompt_display_width);
+        };
+
+        let prompt = format!(
+            "({}{}i-search)`{}': ",
+            if search.failed() { "failed " } else { "" },
+            if search.direction() == SearchDirection::Reverse {
+                "reverse-"
+            } else {
+                ""
+            },
+            search.query()
+        );
+        let prompt_width = display_width(&prompt) as u16;
+        (prompt, prompt_width)
+    }
+
     fn queue_write_outbuff(&mut self) -> Result<(), anyhow::Error> {
         queue!(
             self.handle,
@@ -152,7 +310,7 @@ impl Terminal {
             return Ok(());
         }
 
-        let total_width = self.prompt_display_width + self.get_display_width_up_to_cursor_pos();
+        let total_width = self.active_prompt().1 + self.get_display_width_up_to_cursor_pos();
         let y = total_width / self.term_width;
         let x = total_width % self.term_width;
 
@@ -219,7 +377,7 @@ impl Terminal {
         if self.term_width == 0 {
             return;
         }
-        let total_width = self.prompt_display_width + self.get_display_width_up_to_cursor_pos();
+        let total_width = self.active_prompt().1 + self.get_display_width_up_to_cursor_pos();
         self.cursor_y = total_width / self.term_width;
         self.cursor_x = total_width % self.term_width;
     }
@@ -228,7 +386,7 @@ impl Terminal {
         if self.term_width == 0 {
             return;
         }
-        let total_visual_length = self.prompt_display_width + self.input.display_width() as u16;
+        let total_visual_length = self.active_prompt().1 + self.input.display_width() as u16;
         self.input_end_y = total_visual_length / self.term_width;
         self.input_end_x = total_visual_length % self.term_width;
     }
@@ -1,7 +1,7 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader, Read},
-    path::PathBuf,
+    fs::{self, DirBuilder, File},
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
 };
 
 /// Reads the contents of a file into a string.
@@ -28,6 +28,33 @@ pub fn read_file_lines(file_path: &str) -> Result<Vec<Vec<u8>>, std::io::Error>
     Ok(result)
 }
 
+/// Replaces the contents of a file by writing them to a temporary file first
+/// and renaming it over the original, so readers never see a partial write.
+pub fn write_file_atomically(file_path: &str, contents: &str) -> Result<(), std::io::Error> {
+    let expanded_path = expand_tilde(file_path);
+    let mut tmp_path = expanded_path.clone().into_os_string();
+    tmp_path.push(".tmp");
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, &expanded_path)?;
+    Ok(())
+}
+
+/// Creates a directory along with its parents, only accessible by the owner
+/// as it may hold private data.
+pub fn create_private_dir(dir: &Path) -> Result<(), std::io::Error> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir)
+}
+
 /// Expands a tilde in a file path to the user's home directory.
 fn expand_tilde(path: &str) -> PathBuf {
     if path.starts_with("~") {
@@ -1,10 +1,8 @@
 pub mod fs;
 pub mod ssh;
 
-mod set;
 mod unicode;
 
-pub use set::TimedHashSet;
-pub use unicode::display_width;
+pub use unicode::{display_width, strip_ansi};
 
 pub const NEWLINE: &'static str = "\n\r";
@@ -1,3 +1,5 @@
+use russh_keys::key::PublicKey;
+
 pub fn split_ssh_key(ssh_key_bytes: &[u8]) -> Option<(String, String, String)> {
     // Convert the vector of bytes into a string for easier manipulation
     let ssh_key_string = match String::from_utf8(ssh_key_bytes.to_vec()) {
@@ -20,3 +22,69 @@ pub fn split_ssh_key(ssh_key_bytes: &[u8]) -> Option<(String, String, String)> {
 
     Some((algo, key, name))
 }
+
+/// Reads a file of public keys in the `authorized_keys` format. Lines that
+/// cannot be parsed as a public key are skipped.
+pub fn read_public_keys(file_path: &str) -> Result<Vec<PublicKey>, std::io::Error> {
+    let keys = super::fs::read_file_lines(file_path)?
+        .iter()
+        .filter_map(|line| split_ssh_key(line))
+        .filter_map(|(_, key, _)| russh_keys::parse_public_key_base64(&key).ok())
+        .collect();
+
+    Ok(keys)
+}
+
+/// Formats a public key as an `authorized_keys` line.
+pub fn format_public_key(key: &PublicKey, comment: &str) -> String {
+    use russh_keys::PublicKeyBase64;
+    format!("{} {} {}", key.name(), key.public_key_base64(), comment)
+}
+
+/// Appends a public key to a file in the `authorized_keys` format, creating
+/// the file if it does not exist.
+pub fn append_public_key(
+    file_path: &str,
+    key: &PublicKey,
+    comment: &str,
+) -> Result<(), std::io::Error> {
+    let mut contents = match super::fs::read_file_to_string(file_path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str(&format_public_key(key, comment));
+    contents.push('\n');
+
+    super::fs::write_file_atomically(file_path, &contents)
+}
+
+/// Removes every line holding the given public key from a file in the
+/// `authorized_keys` format. Comments and other lines are kept as is.
+pub fn remove_public_key(file_path: &str, key: &PublicKey) -> Result<(), std::io::Error> {
+    let contents = super::fs::read_file_to_string(file_path)?;
+    let mut result = String::new();
+
+    for line in contents.lines() {
+        let matches = split_ssh_key(line.as_bytes())
+            .and_then(|(_, key, _)| russh_keys::parse_public_key_base64(&key).ok())
+            .is_some_and(|k| k.eq(key));
+
+        if !matches {
+            result.push_str(line);
+            result.push('\n');
+        }
+    }
+
+    super::fs::write_file_atomically(file_path, &result)
+}
+
+/// Turns a base64 key fingerprint into a file name, as it may contain
+/// characters not allowed in file names.
+pub fn fingerprint_file_name(fingerprint: &str) -> String {
+    fingerprint.replace('/', "_").replace('+', "-")
+}
@@ -33,6 +33,19 @@ pub fn display_width(string: &str) -> usize {
     width
 }
 
+/// Removes ANSI escape sequences, e.g. colors, leaving only the printable text.
+pub fn strip_ansi(string: &str) -> String {
+    let mut graphemes = string.graphemes(true);
+    let mut result = String::with_capacity(string.len());
+    while let Some(grapheme) = graphemes.next() {
+        if skip_ansi_escape_sequence(grapheme, &mut graphemes) {
+            continue;
+        }
+        result.push_str(grapheme);
+    }
+    result
+}
+
 /// Calculate the render width of a single Unicode character. Unicode characters may consist of
 /// multiple String characters, which is why the function argument takes a string.
 fn display_width_char(string: &str) -> usize {